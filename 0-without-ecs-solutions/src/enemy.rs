@@ -1,26 +1,30 @@
-use std::time::{Instant, Duration};
+use std::time::Duration;
 
 use sdl2::{
     rect::{Point, Rect},
-    render::{Texture, WindowCanvas},
+    render::Texture,
 };
-use rand::{Rng, thread_rng};
+use rand::Rng;
 
 use crate::direction::Direction;
+use crate::obstacle::{Obstacle, move_around_obstacles};
+use crate::world_canvas::{WorldCanvas, WorldPoint, WorldRect};
 
 pub struct Enemy {
     /// The position of the enemy in world coordinates
     position: Point,
     /// The texture containing the enemy spritesheet
     texture: usize,
+    /// The point in the sprite (relative to its top-left corner) that is drawn at `position`
+    anchor: Point,
     /// The direction of the enemy's movement
     direction: Direction,
-    /// The amount of time elapsed since the direction was changed
-    direction_timer: Instant,
+    /// The amount of game time elapsed since the direction was changed
+    direction_timer: Duration,
     /// The current animation frame for the enemy's walking animation
     frame: i32,
-    /// The amount of time elapsed since the animation frame changed
-    frame_timer: Instant,
+    /// The amount of game time elapsed since the animation frame changed
+    frame_timer: Duration,
 }
 
 impl Enemy {
@@ -29,10 +33,11 @@ impl Enemy {
         Self {
             position,
             texture,
+            anchor: Point::new(32, 43),
             direction,
-            direction_timer: Instant::now(),
+            direction_timer: Duration::from_secs(0),
             frame: 0,
-            frame_timer: Instant::now(),
+            frame_timer: Duration::from_secs(0),
         }
     }
 
@@ -43,8 +48,14 @@ impl Enemy {
         Rect::from_center(self.position, 50, 58)
     }
 
-    /// Update the enemy's state
-    pub fn update(&mut self, time_elapsed: Duration, world_bounds: Rect) {
+    /// Update the enemy's state, using `rng` to decide which direction to walk in
+    pub fn update(
+        &mut self,
+        time_elapsed: Duration,
+        world_bounds: Rect,
+        obstacles: &[Obstacle],
+        rng: &mut impl Rng,
+    ) {
         // The speed of the enemy's movement in pixels/second
         let speed = 200;
 
@@ -56,27 +67,31 @@ impl Enemy {
         //   in time_elapsed fits within the range of i32.
         let distance = speed * time_elapsed.as_micros() as i32 / 1_000_000;
 
-        // Move in the current direction
-        let last_position = self.position;
-        self.position += self.direction.into_point() * distance;
-        // Disallow enemies from leaving the window
-        if !world_bounds.contains_rect(self.bounding_box()) {
-            // Reset to the last position because that is definitely inside the world boundary
-            self.position = last_position;
-        }
+        // Move in the current direction, stopping at the edge of the window or at any obstacles
+        let new_bounds = move_around_obstacles(
+            self.bounding_box(),
+            self.direction.into_point() * distance,
+            world_bounds,
+            obstacles,
+        );
+        self.position = new_bounds.center();
 
         // Advance the walking animation
-        if self.frame_timer.elapsed() >= Duration::from_millis(150) {
+        let frame_delay = Duration::from_millis(150);
+        self.frame_timer += time_elapsed;
+        if self.frame_timer >= frame_delay {
             // Note that this code assumes that ALL walking animations have 3 frames.
             self.frame = (self.frame + 1) % 3;
-            // Reset the frame timer
-            self.frame_timer = Instant::now();
+            // Only remove the time used up by the frame so that any excess counts towards the
+            // next frame. Resetting to zero would cause the animation to slowly drift behind.
+            self.frame_timer -= frame_delay;
         }
 
         // Avoid changing the direction too rapidly by only doing it every so often
-        if self.direction_timer.elapsed() >= Duration::from_millis(200) {
+        let direction_change_delay = Duration::from_millis(200);
+        self.direction_timer += time_elapsed;
+        if self.direction_timer >= direction_change_delay {
             // Generate a new random direction
-            let mut rng = thread_rng();
             self.direction = match rng.gen_range(1, 101) {
                 // 60% probability of staying in the same direction
                 1..=60 => self.direction,
@@ -88,13 +103,13 @@ impl Enemy {
                 _ => unreachable!(),
             };
 
-            // Reset the direction timer
-            self.direction_timer = Instant::now();
+            // Keep any excess time, just like the frame timer above
+            self.direction_timer -= direction_change_delay;
         }
     }
 
     /// Draw the enemy onto the given canvas
-    pub fn render(&self, canvas: &mut WindowCanvas, textures: &[Texture]) -> Result<(), String> {
+    pub fn render(&self, canvas: &mut WorldCanvas, textures: &[Texture]) -> Result<(), String> {
         let (sprite_width, sprite_height) = (64, 72);
         let sprite_x = self.frame * sprite_width;
         let spritesheet_row = match self.direction {
@@ -106,14 +121,10 @@ impl Enemy {
         let sprite_y = spritesheet_row * sprite_height;
         let sprite_rect = Rect::new(sprite_x, sprite_y, sprite_width as u32, sprite_height as u32);
 
-        // The screen coordinate system has (0, 0) in its top-left corner whereas the
-        // world coordinate system has (0, 0) in the center of the screen.
-        let (width, height) = canvas.output_size()?;
-        let screen_pos = self.position + Point::new((width/2) as i32, (height/2) as i32);
-        let screen_rect = Rect::from_center(screen_pos, sprite_width as u32, sprite_height as u32);
+        let world_rect = WorldRect::from_anchor(WorldPoint(self.position), self.anchor, sprite_width as u32, sprite_height as u32);
 
         // Copy the current frame onto the canvas
-        canvas.copy(&textures[self.texture], sprite_rect, screen_rect)?;
+        canvas.copy(&textures[self.texture], sprite_rect, world_rect)?;
 
         Ok(())
     }
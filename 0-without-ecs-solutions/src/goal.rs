@@ -1,13 +1,17 @@
 use sdl2::{
     rect::{Point, Rect},
-    render::{Texture, WindowCanvas},
+    render::Texture,
 };
 
+use crate::world_canvas::{WorldCanvas, WorldPoint, WorldRect};
+
 pub struct Goal {
     /// The position of the goal in world coordinates
     position: Point,
     /// The texture containing the goal spritesheet
     texture: usize,
+    /// The point in the sprite (relative to its top-left corner) that is drawn at `position`
+    anchor: Point,
 }
 
 impl Goal {
@@ -16,6 +20,7 @@ impl Goal {
         Self {
             position,
             texture,
+            anchor: Point::new(64, 66),
         }
     }
 
@@ -27,19 +32,15 @@ impl Goal {
     }
 
     /// Draw the goal onto the given canvas
-    pub fn render(&self, canvas: &mut WindowCanvas, textures: &[Texture]) -> Result<(), String> {
+    pub fn render(&self, canvas: &mut WorldCanvas, textures: &[Texture]) -> Result<(), String> {
         let (sprite_x, sprite_y) = (0, 0);
         let (sprite_width, sprite_height) = (128, 128);
         let sprite_rect = Rect::new(sprite_x, sprite_y, sprite_width, sprite_height);
 
-        // The screen coordinate system has (0, 0) in its top-left corner whereas the
-        // world coordinate system has (0, 0) in the center of the screen.
-        let (width, height) = canvas.output_size()?;
-        let screen_pos = self.position + Point::new((width/2) as i32, (height/2) as i32);
-        let screen_rect = Rect::from_center(screen_pos, sprite_width, sprite_height);
+        let world_rect = WorldRect::from_anchor(WorldPoint(self.position), self.anchor, sprite_width, sprite_height);
 
         // Copy the sprite onto the canvas
-        canvas.copy(&textures[self.texture], sprite_rect, screen_rect)?;
+        canvas.copy(&textures[self.texture], sprite_rect, world_rect)?;
 
         Ok(())
     }
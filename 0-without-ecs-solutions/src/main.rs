@@ -2,17 +2,23 @@ mod direction;
 mod player;
 mod enemy;
 mod goal;
+mod obstacle;
+mod world_canvas;
+// The same spritesheets are used by the version with an ECS
+#[path = "../../1-with-ecs-solutions/src/spritesheet.rs"]
+mod spritesheet;
 
+use std::env;
 use std::thread;
 use std::error::Error;
 use std::time::Duration;
 
-use rand::{Rng, thread_rng};
+use rand::{Rng, SeedableRng, thread_rng, rngs::StdRng};
 use sdl2::{
     event::Event,
     keyboard::Keycode,
     pixels::Color,
-    rect::{Point, Rect},
+    rect::Point,
     image::{self, LoadTexture, InitFlag},
 };
 
@@ -20,8 +26,16 @@ use crate::direction::Direction;
 use crate::player::Player;
 use crate::enemy::Enemy;
 use crate::goal::Goal;
+use crate::obstacle::Obstacle;
+use crate::world_canvas::{WorldCanvas, WorldRect};
+use crate::spritesheet::SpriteSheet;
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // All randomness in the game is derived from this seed. Printing it means that any run of the
+    // game can be reproduced exactly by passing the same seed back in with `--seed`.
+    let seed = seed_from_args()?;
+    println!("Using seed: {}", seed);
+
     // Initialize the SDL2 library
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
@@ -40,21 +54,29 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Load assets
     let texture_creator = canvas.texture_creator();
+    let bardo_sheet = SpriteSheet::load("../1-with-ecs-solutions/assets/bardo_2x.json")?;
     // Store the textures in an array so that they can be referenced by index. This allows textures
     // to be shared between entities without having to copy the texture all over the place.
     let textures = [
-        texture_creator.load_texture("assets/bardo_2x.png")?,
+        texture_creator.load_texture(bardo_sheet.image())?,
         texture_creator.load_texture("assets/reaper_blade_2x.png")?,
         texture_creator.load_texture("assets/pinktrees_2x.png")?,
+        texture_creator.load_texture("assets/trees_2x.png")?,
     ];
     let bardo_texture = 0;
     let reaper_texture = 1;
     let pink_trees_texture = 2;
+    let trees_texture = 3;
 
     // Game state
-    let mut rng = thread_rng();
+    let mut rng = StdRng::seed_from_u64(seed);
     let goal = Goal::new(Point::new(rng.gen_range(-300, 301), -200), pink_trees_texture);
-    let mut player = Player::new(Point::new(rng.gen_range(-320, 321), 250), bardo_texture);
+    let mut player = Player::new(Point::new(rng.gen_range(-320, 321), 250), bardo_texture, &bardo_sheet)?;
+    // Trees on either side of the world that nothing can walk through
+    let obstacles = [
+        Obstacle::new(Point::new(-350, -30), trees_texture),
+        Obstacle::new(Point::new(350, -30), trees_texture),
+    ];
 
     // Generate enemies in random positions. To avoid overlap with anything else, an area of the
     // world coordinate system is divided up into a 2D grid. Each enemy gets a random position
@@ -80,10 +102,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Begin game loop
     let frame_duration = Duration::from_nanos(1_000_000_000 / 60);
     // The boundary of the window in world coordinates
-    let world_bounds = {
-        let (width, height) = canvas.output_size()?;
-        Rect::from_center((0, 0), width, height)
-    };
+    let WorldRect(world_bounds) = WorldCanvas::new(&mut canvas).visible_area()?;
     let mut event_pump = sdl_context.event_pump()?;
     // A labelled loop can be used with `break` even from inside another loop
     'running: loop {
@@ -110,11 +129,19 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Event::KeyDown { keycode: Some(Keycode::Right), repeat: false, .. } => {
                     player.walk_in_direction(Direction::Right);
                 },
-                Event::KeyUp { keycode: Some(Keycode::Left), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Right), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Up), repeat: false, .. } |
+                // Stop moving in the direction of the arrow key that was released. The player will
+                // keep moving if they are still holding another arrow key.
+                Event::KeyUp { keycode: Some(Keycode::Up), repeat: false, .. } => {
+                    player.stop(Direction::Up);
+                },
                 Event::KeyUp { keycode: Some(Keycode::Down), repeat: false, .. } => {
-                    player.stop();
+                    player.stop(Direction::Down);
+                },
+                Event::KeyUp { keycode: Some(Keycode::Left), repeat: false, .. } => {
+                    player.stop(Direction::Left);
+                },
+                Event::KeyUp { keycode: Some(Keycode::Right), repeat: false, .. } => {
+                    player.stop(Direction::Right);
                 },
                 _ => {}
             }
@@ -123,9 +150,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         // UPDATE
 
         // Update game state
-        player.update(frame_duration, world_bounds);
+        player.update(frame_duration, world_bounds, &obstacles);
         for enemy in &mut enemies {
-            enemy.update(frame_duration, world_bounds);
+            enemy.update(frame_duration, world_bounds, &obstacles, &mut rng);
         }
         // If the player collides with any enemies, quit the game immediately
         if enemies.iter().any(|enemy| player.collides_with(enemy.bounding_box())) {
@@ -144,11 +171,16 @@ fn main() -> Result<(), Box<dyn Error>> {
         canvas.set_draw_color(Color::RGB(128, 128, 128));
         canvas.clear();
 
-        player.render(&mut canvas, &textures)?;
+        // Everything in the game is positioned in world coordinates, so draw it that way
+        let mut world_canvas = WorldCanvas::new(&mut canvas);
+        player.render(&mut world_canvas, &textures)?;
         for enemy in &enemies {
-            enemy.render(&mut canvas, &textures)?;
+            enemy.render(&mut world_canvas, &textures)?;
+        }
+        goal.render(&mut world_canvas, &textures)?;
+        for obstacle in &obstacles {
+            obstacle.render(&mut world_canvas, &textures)?;
         }
-        goal.render(&mut canvas, &textures)?;
 
         canvas.present();
 
@@ -169,3 +201,17 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Returns the seed passed with `--seed <seed>` on the command line or a random seed if no seed
+/// was provided
+fn seed_from_args() -> Result<u64, String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            let seed = args.next().ok_or("expected a number after --seed")?;
+            return seed.parse().map_err(|err| format!("invalid seed '{}': {}", seed, err));
+        }
+    }
+
+    Ok(thread_rng().gen())
+}
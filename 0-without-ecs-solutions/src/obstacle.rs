@@ -0,0 +1,117 @@
+use sdl2::{
+    rect::{Point, Rect},
+    render::Texture,
+};
+
+use crate::world_canvas::{WorldCanvas, WorldPoint, WorldRect};
+
+/// A solid piece of scenery (e.g. a tree) that nothing can walk through
+pub struct Obstacle {
+    /// The position of the obstacle in world coordinates
+    position: Point,
+    /// The texture containing the obstacle spritesheet
+    texture: usize,
+    /// The point in the sprite (relative to its top-left corner) that is drawn at `position`
+    anchor: Point,
+}
+
+impl Obstacle {
+    /// Creates a new obstacle
+    pub fn new(position: Point, texture: usize) -> Self {
+        Self {
+            position,
+            texture,
+            anchor: Point::new(48, 68),
+        }
+    }
+
+    /// Returns a rectangle that tightly encompasses the obstacle in the world coordinate system
+    pub fn bounding_box(&self) -> Rect {
+        // This is different from the size of the sprite because we only want the visible region,
+        // not any surrounding transparent pixels
+        Rect::from_center(self.position, 80, 104)
+    }
+
+    /// Draw the obstacle onto the given canvas
+    pub fn render(&self, canvas: &mut WorldCanvas, textures: &[Texture]) -> Result<(), String> {
+        let (sprite_x, sprite_y) = (0, 0);
+        let (sprite_width, sprite_height) = (96, 128);
+        let sprite_rect = Rect::new(sprite_x, sprite_y, sprite_width, sprite_height);
+
+        let world_rect = WorldRect::from_anchor(WorldPoint(self.position), self.anchor, sprite_width, sprite_height);
+
+        // Copy the sprite onto the canvas
+        canvas.copy(&textures[self.texture], sprite_rect, world_rect)?;
+
+        Ok(())
+    }
+}
+
+/// Moves a bounding box by the given displacement without letting it leave `world_bounds` or
+/// overlap any of the obstacles. Returns the bounding box at its new position.
+///
+/// Each axis is moved separately so that something moving diagonally into an obstacle slides
+/// along it instead of stopping completely. Along each axis, the bounding box moves as far as it
+/// can and stops flush against the first thing in its way. Checking the whole path instead of
+/// only the final position means that even very fast movement can't skip over an obstacle.
+pub fn move_around_obstacles(
+    bounds: Rect,
+    displacement: Point,
+    world_bounds: Rect,
+    obstacles: &[Obstacle],
+) -> Rect {
+    let mut bounds = bounds;
+
+    // Only obstacles level with the bounding box can be hit when moving horizontally
+    let dx = sweep_axis(
+        (bounds.left(), bounds.right()),
+        displacement.x(),
+        (world_bounds.left(), world_bounds.right()),
+        obstacles.iter()
+            .map(|obstacle| obstacle.bounding_box())
+            .filter(|obstacle| obstacle.top() < bounds.bottom() && obstacle.bottom() > bounds.top())
+            .map(|obstacle| (obstacle.left(), obstacle.right())),
+    );
+    bounds.offset(dx, 0);
+
+    // Only obstacles in line with the bounding box can be hit when moving vertically
+    let dy = sweep_axis(
+        (bounds.top(), bounds.bottom()),
+        displacement.y(),
+        (world_bounds.top(), world_bounds.bottom()),
+        obstacles.iter()
+            .map(|obstacle| obstacle.bounding_box())
+            .filter(|obstacle| obstacle.left() < bounds.right() && obstacle.right() > bounds.left())
+            .map(|obstacle| (obstacle.top(), obstacle.bottom())),
+    );
+    bounds.offset(0, dy);
+
+    bounds
+}
+
+/// Returns how far something spanning from `start` to `end` along an axis can move by `distance`
+/// along that axis without leaving `limits` or passing into any of the `obstacles` (each given as
+/// the span they cover along the same axis)
+///
+/// Obstacles that are already overlapping are ignored so that it is always possible to walk out of
+/// them.
+fn sweep_axis(
+    (start, end): (i32, i32),
+    distance: i32,
+    (lower_limit, upper_limit): (i32, i32),
+    obstacles: impl Iterator<Item=(i32, i32)>,
+) -> i32 {
+    if distance > 0 {
+        let allowed = obstacles
+            // Only obstacles ahead are in the way
+            .filter(|&(obstacle_start, _)| obstacle_start >= end)
+            .fold(upper_limit - end, |allowed, (obstacle_start, _)| allowed.min(obstacle_start - end));
+        // Never move backwards, even if already past a limit
+        distance.min(allowed.max(0))
+    } else {
+        let allowed = obstacles
+            .filter(|&(_, obstacle_end)| obstacle_end <= start)
+            .fold(lower_limit - start, |allowed, (_, obstacle_end)| allowed.max(obstacle_end - start));
+        distance.max(allowed.min(0))
+    }
+}
@@ -1,38 +1,92 @@
-use std::time::{Instant, Duration};
+use std::time::Duration;
 
 use sdl2::{
     rect::{Point, Rect},
-    render::{Texture, WindowCanvas},
+    render::Texture,
 };
 
 use crate::direction::Direction;
+use crate::spritesheet::{SpriteSheet, Frame};
+use crate::obstacle::{Obstacle, move_around_obstacles};
+use crate::world_canvas::{WorldCanvas, WorldPoint, WorldRect};
 
 pub struct Player {
     /// The position of the player in world coordinates
     position: Point,
     /// The texture containing the player spritesheet
     texture: usize,
+    /// The point in the sprite (relative to its top-left corner) that is drawn at `position`
+    anchor: Point,
     /// The speed of the player's movement in pixels/second (0 = stopped)
     speed: i32,
     /// The direction of the player's movement
     direction: Direction,
+    /// Every direction the player has been told to walk in and not yet told to stop, in the order
+    /// they were given. The player always walks in the most recent one.
+    walk_directions: Vec<Direction>,
+    /// The walking animation for each direction
+    animations: WalkingAnimations,
     /// The current animation frame for the player's walking animation
-    frame: i32,
-    /// The amount of time elapsed since the animation frame changed
-    frame_timer: Instant,
+    frame: usize,
+    /// The amount of game time elapsed since the animation frame changed
+    frame_timer: Duration,
+}
+
+/// The frames of the walking animation in each direction
+struct WalkingAnimations {
+    up: Vec<Frame>,
+    down: Vec<Frame>,
+    left: Vec<Frame>,
+    right: Vec<Frame>,
+}
+
+impl WalkingAnimations {
+    /// Loads the animations tagged `walk_up`, `walk_down`, `walk_left` and `walk_right`
+    fn from_spritesheet(sheet: &SpriteSheet) -> Result<Self, String> {
+        let animation = |tag| sheet.animation(tag)
+            .filter(|frames| !frames.is_empty())
+            .ok_or_else(|| format!("spritesheet '{}' has no '{}' tag", sheet.image().display(), tag));
+
+        Ok(Self {
+            up: animation("walk_up")?,
+            down: animation("walk_down")?,
+            left: animation("walk_left")?,
+            right: animation("walk_right")?,
+        })
+    }
+
+    /// Returns the frames of the animation for walking in the given direction
+    fn for_direction(&self, direction: Direction) -> &[Frame] {
+        match direction {
+            Direction::Up => &self.up,
+            Direction::Down => &self.down,
+            Direction::Left => &self.left,
+            Direction::Right => &self.right,
+        }
+    }
 }
 
 impl Player {
-    /// Creates a new player
-    pub fn new(position: Point, texture: usize) -> Self {
-        Self {
+    /// Creates a new player that is drawn using the walking animations from the given spritesheet
+    pub fn new(position: Point, texture: usize, sheet: &SpriteSheet) -> Result<Self, String> {
+        Ok(Self {
             position,
             texture,
+            anchor: Point::new(25, 43),
             speed: 0,
             direction: Direction::Down,
+            walk_directions: Vec::new(),
+            animations: WalkingAnimations::from_spritesheet(sheet)?,
             frame: 0,
-            frame_timer: Instant::now(),
-        }
+            frame_timer: Duration::from_secs(0),
+        })
+    }
+
+    /// The current frame of the walking animation for the direction the player is facing
+    fn current_frame(&self) -> Frame {
+        let frames = self.animations.for_direction(self.direction);
+        // The animations for each direction may have a different number of frames
+        frames[self.frame % frames.len()]
     }
 
     /// Returns a rectangle that tightly encompasses the player in the world coordinate system
@@ -49,17 +103,30 @@ impl Player {
 
     /// Set the player in motion in the given direction
     pub fn walk_in_direction(&mut self, direction: Direction) {
+        // Move the direction to the end of the list since it is now the most recent
+        self.walk_directions.retain(|&dir| dir != direction);
+        self.walk_directions.push(direction);
+
         self.speed = 200;
         self.direction = direction;
     }
 
-    /// Stop the player's movement but preserve their direction
-    pub fn stop(&mut self) {
-        self.speed = 0;
+    /// Stop walking in the given direction
+    ///
+    /// If the player was told to walk in another direction that hasn't been stopped yet, they
+    /// will go back to walking in that direction. Otherwise, the player's movement will stop but
+    /// their direction will be preserved.
+    pub fn stop(&mut self, direction: Direction) {
+        self.walk_directions.retain(|&dir| dir != direction);
+
+        match self.walk_directions.last() {
+            Some(&prev_direction) => self.direction = prev_direction,
+            None => self.speed = 0,
+        }
     }
 
     /// Update the player's state
-    pub fn update(&mut self, time_elapsed: Duration, world_bounds: Rect) {
+    pub fn update(&mut self, time_elapsed: Duration, world_bounds: Rect, obstacles: &[Obstacle]) {
         if self.speed == 0 {
             return;
         }
@@ -72,45 +139,38 @@ impl Player {
         //   in time_elapsed fits within the range of i32.
         let distance = self.speed * time_elapsed.as_micros() as i32 / 1_000_000;
 
-        // Move in the current direction
-        let last_position = self.position;
-        self.position += self.direction.into_point() * distance;
-        // Disallow the player from leaving the window
-        if !world_bounds.contains_rect(self.bounding_box()) {
-            // Reset to the last position because that is definitely inside the world boundary
-            self.position = last_position;
-        }
+        // Move in the current direction, stopping at the edge of the window or at any obstacles
+        let new_bounds = move_around_obstacles(
+            self.bounding_box(),
+            self.direction.into_point() * distance,
+            world_bounds,
+            obstacles,
+        );
+        self.position = new_bounds.center();
 
         // Advance the walking animation (only want to do this when speed != 0)
-        if self.frame_timer.elapsed() >= Duration::from_millis(150) {
-            // Note that this code assumes that ALL walking animations have 3 frames.
-            self.frame = (self.frame + 1) % 3;
-            // Reset the frame timer
-            self.frame_timer = Instant::now();
+        let frame_delay = self.current_frame().duration;
+        self.frame_timer += time_elapsed;
+        if self.frame_timer >= frame_delay {
+            let frame_count = self.animations.for_direction(self.direction).len();
+            self.frame = (self.frame + 1) % frame_count;
+            // Only remove the time used up by the frame so that any excess counts towards the
+            // next frame. Resetting to zero would cause the animation to slowly drift behind.
+            self.frame_timer -= frame_delay;
         }
     }
 
     /// Draw the player onto the given canvas
-    pub fn render(&self, canvas: &mut WindowCanvas, textures: &[Texture]) -> Result<(), String> {
-        let (sprite_width, sprite_height) = (52, 72);
-        let sprite_x = self.frame * sprite_width;
-        let spritesheet_row = match self.direction {
-            Direction::Up => 3,
-            Direction::Down => 0,
-            Direction::Left => 1,
-            Direction::Right => 2,
-        };
-        let sprite_y = spritesheet_row * sprite_height;
-        let sprite_rect = Rect::new(sprite_x, sprite_y, sprite_width as u32, sprite_height as u32);
-
-        // The screen coordinate system has (0, 0) in its top-left corner whereas the
-        // world coordinate system has (0, 0) in the center of the screen.
-        let (width, height) = canvas.output_size()?;
-        let screen_pos = self.position + Point::new((width/2) as i32, (height/2) as i32);
-        let screen_rect = Rect::from_center(screen_pos, sprite_width as u32, sprite_height as u32);
+    pub fn render(&self, canvas: &mut WorldCanvas, textures: &[Texture]) -> Result<(), String> {
+        let Frame {region, trim_offset, ..} = self.current_frame();
+
+        // The anchor is measured from the top-left corner of the whole frame, but trimming moves the
+        // region within the frame
+        let anchor = self.anchor - trim_offset;
+        let world_rect = WorldRect::from_anchor(WorldPoint(self.position), anchor, region.width(), region.height());
 
         // Copy the current frame onto the canvas
-        canvas.copy(&textures[self.texture], sprite_rect, screen_rect)?;
+        canvas.copy(&textures[self.texture], region, world_rect)?;
 
         Ok(())
     }
@@ -0,0 +1,76 @@
+//! The world and the screen use different coordinate systems. The screen has (0, 0) in the
+//! top-left corner of the window, while the world has (0, 0) in the center of the window. Both use
+//! `Point` and `Rect`, so it is easy to forget to convert from one to the other.
+//!
+//! To prevent that, positions in each coordinate system have their own type and everything is
+//! drawn through a `WorldCanvas`, which only accepts world coordinates and does the conversion to
+//! screen coordinates itself.
+
+use sdl2::{
+    rect::{Point, Rect},
+    render::{Texture, WindowCanvas},
+};
+
+/// A point in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldPoint(pub Point);
+
+/// A rectangle in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldRect(pub Rect);
+
+impl WorldRect {
+    /// Creates a rectangle with the given size that has its `anchor` (relative to its top-left
+    /// corner) at the given point
+    pub fn from_anchor(WorldPoint(point): WorldPoint, anchor: Point, width: u32, height: u32) -> Self {
+        let top_left = point - anchor;
+        WorldRect(Rect::new(top_left.x(), top_left.y(), width, height))
+    }
+}
+
+/// A point in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenPoint(pub Point);
+
+/// A rectangle in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenRect(pub Rect);
+
+/// A canvas that is drawn on using world coordinates
+pub struct WorldCanvas<'a> {
+    canvas: &'a mut WindowCanvas,
+}
+
+impl<'a> WorldCanvas<'a> {
+    /// Wraps a canvas so that it can be drawn on using world coordinates
+    pub fn new(canvas: &'a mut WindowCanvas) -> Self {
+        Self {canvas}
+    }
+
+    /// The area of the world that is visible on the screen
+    pub fn visible_area(&self) -> Result<WorldRect, String> {
+        let (width, height) = self.canvas.output_size()?;
+        // The world coordinate system has (0, 0) in the center of the screen
+        Ok(WorldRect(Rect::from_center((0, 0), width, height)))
+    }
+
+    /// Converts a point in world coordinates to screen coordinates
+    pub fn to_screen_point(&self, WorldPoint(point): WorldPoint) -> Result<ScreenPoint, String> {
+        // The screen coordinate system has (0, 0) in its top-left corner whereas the
+        // world coordinate system has (0, 0) in the center of the screen.
+        let (width, height) = self.canvas.output_size()?;
+        Ok(ScreenPoint(point + Point::new((width/2) as i32, (height/2) as i32)))
+    }
+
+    /// Converts a rectangle in world coordinates to screen coordinates
+    pub fn to_screen_rect(&self, WorldRect(rect): WorldRect) -> Result<ScreenRect, String> {
+        let ScreenPoint(top_left) = self.to_screen_point(WorldPoint(rect.top_left()))?;
+        Ok(ScreenRect(Rect::new(top_left.x(), top_left.y(), rect.width(), rect.height())))
+    }
+
+    /// Copies the given region of a texture onto the given area of the world
+    pub fn copy(&mut self, texture: &Texture, region: Rect, dest: WorldRect) -> Result<(), String> {
+        let ScreenRect(screen_rect) = self.to_screen_rect(dest)?;
+        self.canvas.copy(texture, region, screen_rect)
+    }
+}
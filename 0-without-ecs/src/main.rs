@@ -2,6 +2,7 @@ mod direction;
 mod player;
 mod enemy;
 mod goal;
+mod obstacle;
 
 use std::thread;
 use std::error::Error;
@@ -20,6 +21,7 @@ use crate::direction::Direction;
 use crate::player::Player;
 use crate::enemy::Enemy;
 use crate::goal::Goal;
+use crate::obstacle::Obstacle;
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Initialize the SDL2 library
@@ -46,15 +48,22 @@ fn main() -> Result<(), Box<dyn Error>> {
         texture_creator.load_texture("assets/bardo_2x.png")?,
         texture_creator.load_texture("assets/reaper_blade_2x.png")?,
         texture_creator.load_texture("assets/pinktrees_2x.png")?,
+        texture_creator.load_texture("assets/trees_2x.png")?,
     ];
     let bardo_texture = 0;
     let reaper_texture = 1;
     let pink_trees_texture = 2;
+    let trees_texture = 3;
 
     // Game state
     let mut rng = thread_rng();
     let goal = Goal::new(Point::new(rng.gen_range(-300, 301), -200), pink_trees_texture);
     let mut player = Player::new(Point::new(rng.gen_range(-320, 321), 250), bardo_texture);
+    // Trees on either side of the world that nothing can walk through
+    let obstacles = [
+        Obstacle::new(Point::new(-350, -30), trees_texture),
+        Obstacle::new(Point::new(350, -30), trees_texture),
+    ];
 
     // Generate enemies in random positions. To avoid overlap with anything else, an area of the
     // world coordinate system is divided up into a 2D grid. Each enemy gets a random position
@@ -111,15 +120,20 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                 //TODO(EX#1): Add two more cases for the left arrow and the right arrow
 
-                Event::KeyUp { keycode: Some(Keycode::Left), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Right), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Up), repeat: false, .. } |
+                // Stop moving in the direction of the arrow key that was released. The player will
+                // keep moving if they are still holding another arrow key.
+                Event::KeyUp { keycode: Some(Keycode::Up), repeat: false, .. } => {
+                    player.stop(Direction::Up);
+                },
+
                 Event::KeyUp { keycode: Some(Keycode::Down), repeat: false, .. } => {
                     //TODO(EX#1): Fill in this line.
                     // HINT: Look at the methods on the player struct. What should happen when the
                     //  player releases the arrow key they had previously pressed down?
                 },
 
+                //TODO(EX#1): Add two more cases for releasing the left arrow and the right arrow
+
                 _ => {}
             }
         }
@@ -127,9 +141,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         // UPDATE
 
         // Update game state
-        player.update(frame_duration, world_bounds);
+        player.update(frame_duration, world_bounds, &obstacles);
         for enemy in &mut enemies {
-            enemy.update(frame_duration, world_bounds);
+            enemy.update(frame_duration, world_bounds, &obstacles, &mut rng);
         }
         // If the player collides with any enemies, quit the game immediately
         if enemies.iter().any(|enemy| player.collides_with(enemy.bounding_box())) {
@@ -153,6 +167,9 @@ fn main() -> Result<(), Box<dyn Error>> {
             enemy.render(&mut canvas, &textures)?;
         }
         goal.render(&mut canvas, &textures)?;
+        for obstacle in &obstacles {
+            obstacle.render(&mut canvas, &textures)?;
+        }
 
         canvas.present();
 
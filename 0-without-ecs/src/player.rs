@@ -1,6 +1,6 @@
 #![allow(dead_code)] //TODO(EX#1): Remove this line
 
-use std::time::{Instant, Duration};
+use std::time::Duration;
 
 use sdl2::{
     rect::{Point, Rect},
@@ -8,20 +8,26 @@ use sdl2::{
 };
 
 use crate::direction::Direction;
+use crate::obstacle::{Obstacle, move_around_obstacles};
 
 pub struct Player {
     /// The position of the player in world coordinates
     position: Point,
     /// The texture containing the player spritesheet
     texture: usize,
+    /// The point in the sprite (relative to its top-left corner) that is drawn at `position`
+    anchor: Point,
     /// The speed of the player's movement in pixels/second (0 = stopped)
     speed: i32,
     /// The direction of the player's movement
     direction: Direction,
+    /// Every direction the player has been told to walk in and not yet told to stop, in the order
+    /// they were given. The player always walks in the most recent one.
+    walk_directions: Vec<Direction>,
     /// The current animation frame for the player's walking animation
     frame: i32,
-    /// The amount of time elapsed since the animation frame changed
-    frame_timer: Instant,
+    /// The amount of game time elapsed since the animation frame changed
+    frame_timer: Duration,
 }
 
 impl Player {
@@ -30,10 +36,12 @@ impl Player {
         Self {
             position,
             texture,
+            anchor: Point::new(25, 43),
             speed: 0,
             direction: Direction::Down,
+            walk_directions: Vec::new(),
             frame: 0,
-            frame_timer: Instant::now(),
+            frame_timer: Duration::from_secs(0),
         }
     }
 
@@ -51,17 +59,30 @@ impl Player {
 
     /// Set the player in motion in the given direction
     pub fn walk_in_direction(&mut self, direction: Direction) {
+        // Move the direction to the end of the list since it is now the most recent
+        self.walk_directions.retain(|&dir| dir != direction);
+        self.walk_directions.push(direction);
+
         self.speed = 200;
         self.direction = direction;
     }
 
-    /// Stop the player's movement but preserve their direction
-    pub fn stop(&mut self) {
-        self.speed = 0;
+    /// Stop walking in the given direction
+    ///
+    /// If the player was told to walk in another direction that hasn't been stopped yet, they
+    /// will go back to walking in that direction. Otherwise, the player's movement will stop but
+    /// their direction will be preserved.
+    pub fn stop(&mut self, direction: Direction) {
+        self.walk_directions.retain(|&dir| dir != direction);
+
+        match self.walk_directions.last() {
+            Some(&prev_direction) => self.direction = prev_direction,
+            None => self.speed = 0,
+        }
     }
 
     /// Update the player's state
-    pub fn update(&mut self, time_elapsed: Duration, world_bounds: Rect) {
+    pub fn update(&mut self, time_elapsed: Duration, world_bounds: Rect, obstacles: &[Obstacle]) {
         if self.speed == 0 {
             return;
         }
@@ -74,21 +95,24 @@ impl Player {
         //   in time_elapsed fits within the range of i32.
         let distance = self.speed * time_elapsed.as_micros() as i32 / 1_000_000;
 
-        // Move in the current direction
-        let last_position = self.position;
-        self.position += self.direction.into_point() * distance;
-        // Disallow the player from leaving the window
-        if !world_bounds.contains_rect(self.bounding_box()) {
-            // Reset to the last position because that is definitely inside the world boundary
-            self.position = last_position;
-        }
+        // Move in the current direction, stopping at the edge of the window or at any obstacles
+        let new_bounds = move_around_obstacles(
+            self.bounding_box(),
+            self.direction.into_point() * distance,
+            world_bounds,
+            obstacles,
+        );
+        self.position = new_bounds.center();
 
         // Advance the walking animation (only want to do this when speed != 0)
-        if self.frame_timer.elapsed() >= Duration::from_millis(150) {
+        let frame_delay = Duration::from_millis(150);
+        self.frame_timer += time_elapsed;
+        if self.frame_timer >= frame_delay {
             // Note that this code assumes that ALL walking animations have 3 frames.
             self.frame = (self.frame + 1) % 3;
-            // Reset the frame timer
-            self.frame_timer = Instant::now();
+            // Only remove the time used up by the frame so that any excess counts towards the
+            // next frame. Resetting to zero would cause the animation to slowly drift behind.
+            self.frame_timer -= frame_delay;
         }
     }
 
@@ -109,7 +133,8 @@ impl Player {
         // world coordinate system has (0, 0) in the center of the screen.
         let (width, height) = canvas.output_size()?;
         let screen_pos = self.position + Point::new((width/2) as i32, (height/2) as i32);
-        let screen_rect = Rect::from_center(screen_pos, sprite_width as u32, sprite_height as u32);
+        let top_left = screen_pos - self.anchor;
+        let screen_rect = Rect::new(top_left.x(), top_left.y(), sprite_width as u32, sprite_height as u32);
 
         // Copy the current frame onto the canvas
         canvas.copy(&textures[self.texture], sprite_rect, screen_rect)?;
@@ -0,0 +1,62 @@
+use serde::Deserialize;
+
+use crate::direction::Direction;
+
+/// Represents something the player can ask the game to do, independent of which key was used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    /// Pause or resume the game
+    Pause,
+    /// Advance the game by a single step while it is paused
+    StepOnce,
+    /// Run the game at 0.25x speed
+    QuarterSpeed,
+    /// Run the game at 0.5x speed
+    HalfSpeed,
+    /// Run the game at normal speed
+    NormalSpeed,
+    /// Run the game at 2x speed
+    DoubleSpeed,
+    Quit,
+    /// Make everything on screen bigger
+    ZoomIn,
+    /// Make everything on screen smaller
+    ZoomOut,
+}
+
+impl Action {
+    /// Every action, in a fixed order that must never change since it is used to identify
+    /// actions in recorded replay files
+    pub const ALL: [Action; 13] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Pause,
+        Action::StepOnce,
+        Action::QuarterSpeed,
+        Action::HalfSpeed,
+        Action::NormalSpeed,
+        Action::DoubleSpeed,
+        Action::Quit,
+        Action::ZoomIn,
+        Action::ZoomOut,
+    ];
+
+    /// Returns the direction of movement requested by this action, if any
+    pub fn direction(self) -> Option<Direction> {
+        use Action::*;
+        match self {
+            MoveUp => Some(Direction::Up),
+            MoveDown => Some(Direction::Down),
+            MoveLeft => Some(Direction::Left),
+            MoveRight => Some(Direction::Right),
+            Pause | StepOnce | QuarterSpeed | HalfSpeed | NormalSpeed | DoubleSpeed | Quit |
+            ZoomIn | ZoomOut => None,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Key bindings are loaded from a configuration file so that players can change the controls
+//! without recompiling the game. The file maps each action to a list of SDL key names, e.g.
+//!
+//! ```ron
+//! {
+//!     MoveUp: ["Up", "W"],
+//!     Quit: ["Escape"],
+//! }
+//! ```
+//!
+//! See the "Key Name" column of https://wiki.libsdl.org/SDL_Keycode for the names of every key.
+
+use std::fs;
+use std::path::Path;
+use std::collections::{HashMap, HashSet};
+
+use sdl2::keyboard::Keycode;
+
+use crate::action::Action;
+
+/// Maps keys on the keyboard to the actions that they trigger
+///
+/// More than one key can be bound to the same action. An action is considered held for as long as
+/// any of the keys bound to it are held.
+#[derive(Debug)]
+pub struct Bindings {
+    /// The action triggered by each key
+    actions: HashMap<Keycode, Action>,
+    /// The keys currently held down that are bound to an action
+    held_keys: HashSet<Keycode>,
+}
+
+impl Bindings {
+    /// Loads the key bindings from the given configuration file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)
+            .map_err(|err| format!("unable to read key bindings from '{}': {}", path.display(), err))?;
+        let keys_for_actions: HashMap<Action, Vec<String>> = ron::de::from_str(&config)
+            .map_err(|err| format!("invalid key bindings in '{}': {}", path.display(), err))?;
+
+        let mut actions = HashMap::new();
+        for (action, key_names) in keys_for_actions {
+            for key_name in key_names {
+                let keycode = Keycode::from_name(&key_name)
+                    .ok_or_else(|| format!("unknown key '{}' bound to {:?} in '{}'", key_name, action, path.display()))?;
+
+                if let Some(other_action) = actions.insert(keycode, action) {
+                    return Err(format!("key '{}' is bound to both {:?} and {:?} in '{}'",
+                        key_name, other_action, action, path.display()));
+                }
+            }
+        }
+
+        Ok(Self {actions, held_keys: HashSet::new()})
+    }
+
+    /// Records that the given key was pressed
+    ///
+    /// Returns the action bound to the key if this press caused the action to begin. Returns None
+    /// if the key isn't bound to anything or if another key bound to the same action is already
+    /// being held.
+    pub fn key_down(&mut self, keycode: Keycode) -> Option<Action> {
+        let action = *self.actions.get(&keycode)?;
+        let already_held = self.is_held(action);
+        self.held_keys.insert(keycode);
+
+        if already_held { None } else { Some(action) }
+    }
+
+    /// Records that the given key was released
+    ///
+    /// Returns the action bound to the key if this release caused the action to end. Returns None
+    /// if the key isn't bound to anything or if another key bound to the same action is still
+    /// being held.
+    pub fn key_up(&mut self, keycode: Keycode) -> Option<Action> {
+        let action = *self.actions.get(&keycode)?;
+        if !self.held_keys.remove(&keycode) {
+            // Key was pressed before we started tracking it (e.g. before the window had focus)
+            return None;
+        }
+
+        if self.is_held(action) { None } else { Some(action) }
+    }
+
+    /// Returns true if any of the keys bound to the given action are held down
+    fn is_held(&self, action: Action) -> bool {
+        self.held_keys.iter().any(|keycode| self.actions.get(keycode) == Some(&action))
+    }
+}
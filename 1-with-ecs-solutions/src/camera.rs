@@ -0,0 +1,143 @@
+//! The camera decides which part of the world is visible in the window. It is stored as a
+//! resource so that systems can control it (e.g. to follow the player) while the renderer uses it
+//! to convert world coordinates into screen coordinates.
+
+use std::time::Duration;
+
+use sdl2::rect::Rect;
+use specs::Entity;
+
+use crate::vector::Vector;
+
+/// The smallest and largest zoom levels allowed
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 4.0;
+
+#[derive(Debug)]
+pub struct Camera {
+    /// The point in world coordinates shown at the center of the screen
+    position: Vector,
+    /// The position of the camera at the start of the most recent simulation step
+    ///
+    /// Used by the renderer to interpolate the camera's motion just like it does for entities.
+    previous_position: Vector,
+    /// The number of screen pixels used to draw each world pixel (e.g. 2.0 = twice as big)
+    zoom: f64,
+    /// The size (in screen pixels) of the area that the camera draws onto
+    viewport: (u32, u32),
+    /// The camera never shows anything outside of these bounds (in world coordinates), unless the
+    /// whole level fits on the screen
+    level_bounds: Rect,
+    /// The entity that the camera follows, if any
+    pub target: Option<Entity>,
+    /// How far (in world pixels) the target can move away from the center of the screen along
+    /// each axis before the camera starts to move
+    pub dead_zone: Vector,
+    /// How quickly the camera catches up to where it should be. Larger values catch up faster.
+    /// Each second, the remaining distance shrinks by a factor of e^smoothing. A value of zero
+    /// disables smoothing so the camera moves instantly.
+    pub smoothing: f64,
+}
+
+impl Camera {
+    /// Creates a camera centered on the given level
+    pub fn new(level_bounds: Rect, viewport: (u32, u32)) -> Self {
+        let center = level_bounds.center();
+        let position = Vector::new(center.x() as f64, center.y() as f64);
+        Self {
+            position,
+            previous_position: position,
+            zoom: 1.0,
+            viewport,
+            level_bounds,
+            target: None,
+            dead_zone: Vector::new(64.0, 48.0),
+            smoothing: 8.0,
+        }
+    }
+
+    /// The position of the camera interpolated between the previous and current simulation steps
+    pub fn interpolated_position(&self, alpha: f64) -> Vector {
+        self.previous_position + (self.position - self.previous_position) * alpha
+    }
+
+    /// The number of screen pixels used to draw each world pixel
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Multiplies the zoom by the given factor, within the allowed range of zoom levels
+    pub fn zoom_by(&mut self, factor: f64) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        // Zooming out may have revealed something outside of the level
+        self.position = self.clamp(self.position);
+        self.previous_position = self.clamp(self.previous_position);
+    }
+
+    /// Moves the camera immediately to the given position, without any smoothing
+    pub fn jump_to(&mut self, position: Vector) {
+        self.position = self.clamp(position);
+        self.previous_position = self.position;
+    }
+
+    /// Moves the camera towards the position of its target (if it has one) after the given amount
+    /// of game time has elapsed
+    pub fn update(&mut self, target: Option<Vector>, time_elapsed: Duration) {
+        self.previous_position = self.position;
+        let target = match target {
+            Some(target) => target,
+            // Nothing to follow, so stay put
+            None => return,
+        };
+
+        // Only move far enough to bring the target back inside the dead zone
+        let offset = target - self.position;
+        let outside_dead_zone = Vector::new(
+            beyond(offset.x, self.dead_zone.x),
+            beyond(offset.y, self.dead_zone.y),
+        );
+        let goal = self.clamp(self.position + outside_dead_zone);
+
+        // Cover a fraction of the remaining distance each step. Using an exponential means that
+        // the camera moves the same way no matter how long each step is.
+        let catch_up = if self.smoothing > 0.0 {
+            1.0 - (-self.smoothing * time_elapsed.as_secs_f64()).exp()
+        } else {
+            1.0
+        };
+        self.position = self.position + (goal - self.position) * catch_up;
+    }
+
+    /// Returns the closest position to the given one where the camera doesn't show anything
+    /// outside of the level
+    fn clamp(&self, position: Vector) -> Vector {
+        // Half the size of the area visible on screen, in world coordinates
+        let half_view = Vector::new(self.viewport.0 as f64, self.viewport.1 as f64) * (0.5 / self.zoom);
+        let level = self.level_bounds;
+        Vector::new(
+            clamp_axis(position.x, half_view.x, level.left() as f64, level.right() as f64),
+            clamp_axis(position.y, half_view.y, level.top() as f64, level.bottom() as f64),
+        )
+    }
+}
+
+/// Returns how far `offset` is beyond the range -limit..=limit (zero if within that range)
+fn beyond(offset: f64, limit: f64) -> f64 {
+    if offset > limit {
+        offset - limit
+    } else if offset < -limit {
+        offset + limit
+    } else {
+        0.0
+    }
+}
+
+/// Clamps the center of a view spanning `half_view` on each side so that it stays between `min`
+/// and `max`. If the view is bigger than that range, it is centered on the range instead.
+fn clamp_axis(center: f64, half_view: f64, min: f64, max: f64) -> f64 {
+    if max - min <= half_view * 2.0 {
+        (min + max) / 2.0
+    } else {
+        center.max(min + half_view).min(max - half_view)
+    }
+}
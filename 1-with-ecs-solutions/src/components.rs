@@ -1,28 +1,139 @@
 use std::sync::Arc;
-use std::time::{Instant, Duration};
+use std::ops::BitOr;
+use std::time::Duration;
+use std::collections::HashMap;
 
-use sdl2::rect::Rect;
-use specs::{Component, VecStorage, NullStorage};
+use sdl2::rect::{Point, Rect};
+use serde::Deserialize;
+use specs::{Component, VecStorage, NullStorage, ReadStorage, Entity};
 
 use crate::direction::Direction;
+use crate::vector::Vector;
+use crate::spritesheet::{self, SpriteSheet};
 
-/// The position and dimensions of an entity in world coordinates
+/// The position of an entity in world coordinates
 ///
-/// Assumes that the entity is positioned at the center of the bounding box.
+/// This is the exact position of the entity, including any fraction of a pixel. Entities that
+/// move slowly may take several steps to move a whole pixel, so this is what should be updated
+/// when moving an entity. The entity's `BoundingBox` is then derived from it.
+///
+/// For characters and scenery, the position is where they touch the ground (the bottom center of
+/// the visible part of their sprite). The `Sprite` and `Collider` are each positioned relative to
+/// this point, so they can line up with each other no matter how the spritesheet is laid out.
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
-pub struct BoundingBox(pub Rect);
+pub struct Position(pub Vector);
+
+/// The position of an entity at the start of the most recent simulation step
+///
+/// Used by the renderer to interpolate between the previous and current positions of an entity
+/// when a frame is drawn part of the way through a step. Maintained by the `Movement` system.
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct PreviousPosition(pub Vector);
 
-/// Allows an entity to move with the given speed in the given direction
+/// The shape of an entity used for collision detection, relative to its `Position`
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
-pub struct Velocity {
-    /// The speed of the entity's movement
-    pub speed: i32,
-    /// The direction of the entity's movement
-    pub direction: Direction,
+pub struct Collider {
+    /// The width (in pixels) of the collider
+    pub width: u32,
+    /// The height (in pixels) of the collider
+    pub height: u32,
+    /// The offset from the entity's position to the center of the collider
+    pub offset: Vector,
+}
+
+impl Collider {
+    /// Returns the bounding box of this collider for an entity at the given position
+    pub fn bounds_at(&self, position: Vector) -> Rect {
+        // The bounding box can only be placed at whole pixels
+        Rect::from_center((position + self.offset).to_point(), self.width, self.height)
+    }
+}
+
+/// Controls which other entities an entity can collide with
+///
+/// Two entities only collide if each one is on a layer that is in the other's mask. An entity
+/// without this component is on every layer and collides with everything.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+#[storage(VecStorage)]
+pub struct CollisionFilter {
+    /// The layers that this entity is on
+    pub layers: Layers,
+    /// The layers that this entity can collide with
+    pub mask: Layers,
+}
+
+impl Default for CollisionFilter {
+    fn default() -> Self {
+        Self {
+            layers: Layers::ALL,
+            mask: Layers::ALL,
+        }
+    }
+}
+
+impl CollisionFilter {
+    /// Returns the collision filter of the given entity
+    pub fn of(filters: &ReadStorage<Self>, entity: Entity) -> Self {
+        filters.get(entity).copied().unwrap_or_default()
+    }
+
+    /// Returns true if an entity with this filter can collide with an entity with the other filter
+    pub fn collides_with(self, other: Self) -> bool {
+        self.mask.intersects(other.layers) && other.mask.intersects(self.layers)
+    }
+}
+
+/// A set of collision layers, combined using `|`
+///
+/// Each layer is identified by its index. The layers are given names in `prefabs.ron`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layers(u32);
+
+impl Layers {
+    pub const NONE: Self = Layers(0);
+    pub const ALL: Self = Layers(!0);
+    /// The number of different layers there can be
+    pub const COUNT: usize = 32;
+
+    /// Returns the set containing only the layer with the given index
+    pub fn single(index: usize) -> Self {
+        assert!(index < Self::COUNT, "bug: there are only {} collision layers", Self::COUNT);
+        Layers(1 << index)
+    }
+
+    /// Returns true if any layer is in both sets
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
 }
 
+impl BitOr for Layers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Layers(self.0 | other.0)
+    }
+}
+
+/// The area occupied by an entity in world coordinates, used for collision detection
+///
+/// This is the entity's `Collider` placed at its `Position`. It is kept up to date with the
+/// position by the `Movement` system.
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct BoundingBox(pub Rect);
+
+/// Allows an entity to move with the given velocity (in pixels per second)
+///
+/// The direction of the entity's movement is the direction of the vector and its speed is the
+/// length of the vector. An entity with a zero velocity is not moving.
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct Velocity(pub Vector);
+
 /// The sprite to render for a given entity. The entity must also have a
 /// `Position` component in order for it to be drawn on the screen.
 #[derive(Component, Debug, Clone, PartialEq, Eq)]
@@ -32,6 +143,9 @@ pub struct Sprite {
     pub texture_id: usize,
     /// The region of the spritesheet to copy
     pub region: Rect,
+    /// The point in the region (relative to its top-left corner) that should be drawn at the
+    /// entity's `Position`
+    pub anchor: Point,
 }
 
 /// A sequence of sprites that will be used to update an entity's `Sprite` component
@@ -44,11 +158,128 @@ pub struct Animation {
     pub frames: Arc<Vec<Frame>>,
     /// The current animation frame
     pub current_frame: usize,
-    /// The amount of time elapsed since the animation frame changed
-    pub frame_timer: Instant,
+    /// The game time (from the `GameClock` resource) at which the current frame began
+    pub frame_timer: Duration,
+    /// If set, the animation advances each time the entity travels this many pixels instead of
+    /// when each frame's duration has passed, so that walking feet stay planted at any speed
+    pub stride: Option<f64>,
+    /// The distance (in pixels) travelled since the current frame began. Only used with a `stride`.
+    pub distance: f64,
+    /// What happens once the last frame has been shown
+    pub playback: Playback,
+    /// True while a `PingPong` animation is going back through its frames
+    pub reversing: bool,
+    /// True once a one-shot animation has shown its last frame for its full duration
+    pub finished: bool,
+    /// The animation to go back to once a `Once` animation has finished. If there isn't one, the
+    /// `Animation` component is removed instead (leaving the last frame as the entity's sprite).
+    pub previous: Option<Box<Animation>>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Animation {
+    /// Creates an animation that starts at its first frame
+    pub fn new(frames: Vec<Frame>, playback: Playback) -> Self {
+        Self {
+            frames: Arc::new(frames),
+            current_frame: 0,
+            frame_timer: Duration::from_secs(0),
+            stride: None,
+            distance: 0.0,
+            playback,
+            reversing: false,
+            finished: false,
+            previous: None,
+        }
+    }
+
+    /// Loads the animation with the given tag name from a spritesheet, if there is one
+    ///
+    /// The `anchor` is the point in each frame (before trimming) that is drawn at the entity's
+    /// position.
+    pub fn from_spritesheet(sheet: &SpriteSheet, tag: &str, texture_id: usize, anchor: Point) -> Option<Self> {
+        let frames = sheet.animation(tag)?.into_iter().map(|spritesheet::Frame {region, trim_offset, duration}| {
+            Frame {
+                sprite: Sprite {
+                    texture_id,
+                    region,
+                    // Trimming moves the region, so the anchor has to be moved with it
+                    anchor: anchor - trim_offset,
+                },
+                duration,
+            }
+        }).collect();
+
+        Some(Self::new(frames, Playback::Loop))
+    }
+
+    /// Starts the animation over from its first frame at the game time `now`
+    pub fn start(&mut self, now: Duration) {
+        self.current_frame = 0;
+        self.frame_timer = now;
+        self.distance = 0.0;
+        self.reversing = false;
+        self.finished = false;
+    }
+
+    /// Returns true if this animation is only played once instead of repeating
+    pub fn is_one_shot(&self) -> bool {
+        match self.playback {
+            Playback::Loop | Playback::PingPong => false,
+            Playback::Once | Playback::OnceThenHold => true,
+        }
+    }
+
+    /// Moves on to the next frame based on the playback mode. Returns false (without changing the
+    /// current frame) if a one-shot animation is already on its last frame.
+    pub fn advance(&mut self) -> bool {
+        let last_frame = self.frames.len() - 1;
+        match self.playback {
+            Playback::Loop => {
+                // Loop back to the first frame if we've advanced past the end
+                self.current_frame = (self.current_frame + 1) % self.frames.len();
+            },
+            Playback::PingPong => {
+                // Turn around at either end so that the end frames aren't shown twice in a row
+                if self.current_frame == last_frame {
+                    self.reversing = true;
+                } else if self.current_frame == 0 {
+                    self.reversing = false;
+                }
+
+                self.current_frame = if self.reversing {
+                    self.current_frame.saturating_sub(1)
+                } else {
+                    (self.current_frame + 1).min(last_frame)
+                };
+            },
+            Playback::Once | Playback::OnceThenHold => {
+                if self.current_frame == last_frame {
+                    return false;
+                }
+                self.current_frame += 1;
+            },
+        }
+
+        true
+    }
+}
+
+/// The ways that an animation can be played
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Playback {
+    /// Starts over from the first frame after the last one (e.g. for walking)
+    #[default]
+    Loop,
+    /// Goes back and forth between the first and last frames
+    PingPong,
+    /// Plays each frame once and then the animation is removed, or replaced by the one it
+    /// interrupted (e.g. for attacks)
+    Once,
+    /// Plays each frame once and then stays on the last frame (e.g. for dying)
+    OnceThenHold,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Frame {
     /// The sprite to render for this frame
     pub sprite: Sprite,
@@ -57,67 +288,236 @@ pub struct Frame {
     pub duration: Duration,
 }
 
-/// Causes an entity's `Animation` component to be updated based on the direction in
-/// its `Velocity` component.
+/// The things that an entity with `AnimationStates` can be doing, each with its own animation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+pub enum AnimationState {
+    /// Standing still
+    Idle,
+    /// Moving in the direction the entity is facing
+    Walk,
+    /// Played when triggered by an `AnimationTrigger` event, then returns to idle or walking
+    Attack,
+    /// Played when triggered by an `AnimationTrigger` event, then returns to idle or walking
+    Hurt,
+    /// Played once the entity has the `Dead` component. Entities never leave this state.
+    Dead,
+}
+
+/// Causes an entity's `Animation` component to be updated based on what it is doing and the
+/// direction it is facing
+///
+/// See the `Animator` system for how the entity moves between states.
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
-pub struct MovementAnimations {
-    /// The animation for when an entity is moving in the "up" direction
-    pub walking_up: Animation,
-    /// The animation for when an entity is moving in the "down" direction
-    pub walking_down: Animation,
-    /// The animation for when an entity is moving in the "left" direction
-    pub walking_left: Animation,
-    /// The animation for when an entity is moving in the "right" direction
-    pub walking_right: Animation,
-}
-
-impl MovementAnimations {
-    /// Generates a set of movement animations based on the standard convention for spritesheets
-    /// in this project. Expects 4 rows of `frames_length` frames each with the animations ordered:
-    /// walking down, walking left, walking right, walking up.
+pub struct AnimationStates {
+    /// What the entity is currently doing
+    pub state: AnimationState,
+    /// The direction the entity is facing. Entities keep facing the way they were moving after
+    /// they stop so that they don't turn around when standing still.
+    pub facing: Direction,
+    /// The game time (from the `GameClock` resource) at which the current state began
+    pub state_timer: Duration,
+    /// The animation for each state. There is always one for `Idle` and `Walk`. States without an
+    /// animation are skipped, except for `Dead`, which just stops animating.
+    pub clips: HashMap<AnimationState, DirectionalAnimation>,
+    /// Played instead of the idle animation once the entity has been idle for a while
+    pub idle_animation: Option<DelayedAnimation>,
+    /// The state (and the game time it began) that the current `Once` action interrupted, which
+    /// is gone back to along with its animation once the action finishes
+    pub interrupted: Option<(AnimationState, Duration)>,
+}
+
+impl AnimationStates {
+    /// Creates the states for an entity that starts out idle, facing the given direction
+    ///
+    /// Until another idle animation is added, the entity stands still on the first frame of its
+    /// walking animation.
+    pub fn new(walk: DirectionalAnimation, facing: Direction) -> Self {
+        let mut clips = HashMap::new();
+        clips.insert(AnimationState::Idle, walk.first_frames());
+        clips.insert(AnimationState::Walk, walk);
+
+        Self {
+            state: AnimationState::Idle,
+            facing,
+            state_timer: Duration::from_secs(0),
+            clips,
+            idle_animation: None,
+            interrupted: None,
+        }
+    }
+
+    /// Returns the animation for the current state and direction, if the state has one
+    pub fn current_clip(&self) -> Option<&Animation> {
+        self.clips.get(&self.state).map(|clip| clip.animation_for(self.facing))
+    }
+}
+
+/// An animation that is only played after some time has passed
+#[derive(Debug, Clone)]
+pub struct DelayedAnimation {
+    pub animation: DirectionalAnimation,
+    /// The amount of game time that must pass before the animation is played
+    pub delay: Duration,
+}
+
+/// An animation with a different version for each direction that an entity can face
+#[derive(Debug, Clone)]
+pub struct DirectionalAnimation {
+    /// The animation for when an entity is facing "up"
+    pub up: Animation,
+    /// The animation for when an entity is facing "down"
+    pub down: Animation,
+    /// The animation for when an entity is facing "left"
+    pub left: Animation,
+    /// The animation for when an entity is facing "right"
+    pub right: Animation,
+    /// The animation for when an entity is facing diagonally up and to the left, if the
+    /// spritesheet provides one
+    pub up_left: Option<Animation>,
+    /// The animation for when an entity is facing diagonally up and to the right, if the
+    /// spritesheet provides one
+    pub up_right: Option<Animation>,
+    /// The animation for when an entity is facing diagonally down and to the left, if the
+    /// spritesheet provides one
+    pub down_left: Option<Animation>,
+    /// The animation for when an entity is facing diagonally down and to the right, if the
+    /// spritesheet provides one
+    pub down_right: Option<Animation>,
+}
+
+impl DirectionalAnimation {
+    /// Generates a walking animation based on the standard convention for spritesheets in this
+    /// project. Expects 4 rows of `frames_length` frames each with the animations ordered: walking
+    /// down, walking left, walking right, walking up.
     ///
     /// The `top_left_frame` parameter provides the offset in the spritesheet as well as the
-    /// width and height (in pixels) of each frame. The `step_delay` is the duration of each frame.
+    /// width and height (in pixels) of each frame. The `anchor` is the point in each frame that is
+    /// drawn at the entity's position. The `step_delay` is the duration of each frame.
     pub fn standard_walking_animations(
         texture_id: usize,
         top_left_frame: Rect,
+        anchor: Point,
         frames_length: usize,
         step_delay: Duration,
     ) -> Self {
-        let animation = |row| Animation {
-            frames: Arc::new((0..frames_length as i32).map(|frame| Frame {
-                sprite: Sprite {
-                    texture_id,
-                    region: {
-                        let mut frame_region = top_left_frame.clone();
-                        frame_region.offset(
-                            frame * top_left_frame.width() as i32,
-                            row * top_left_frame.height() as i32,
-                        );
-                        frame_region
-                    },
+        let animation = |row| Animation::new((0..frames_length as i32).map(|frame| Frame {
+            sprite: Sprite {
+                texture_id,
+                region: {
+                    let mut frame_region = top_left_frame.clone();
+                    frame_region.offset(
+                        frame * top_left_frame.width() as i32,
+                        row * top_left_frame.height() as i32,
+                    );
+                    frame_region
                 },
-                duration: step_delay,
-            }).collect()),
-            current_frame: 0,
-            frame_timer: Instant::now(),
-        };
+                anchor,
+            },
+            duration: step_delay,
+        }).collect(), Playback::Loop);
 
         Self {
-            walking_up: animation(3),
-            walking_down: animation(0),
-            walking_left: animation(1),
-            walking_right: animation(2),
+            up: animation(3),
+            down: animation(0),
+            left: animation(1),
+            right: animation(2),
+            // The standard spritesheets only have rows for the four main directions
+            up_left: None,
+            up_right: None,
+            down_left: None,
+            down_right: None,
+        }
+    }
+
+    /// Loads an animation from a spritesheet with a tag for each of the four main directions,
+    /// named after the given tag (e.g. `walk_up`, `walk_down`, `walk_left` and `walk_right` for
+    /// `walk`). Tags for the diagonal directions (e.g. `walk_up_left`) are optional.
+    ///
+    /// If there are no tags for any of the directions, the tag with the given name is used for
+    /// every direction instead.
+    ///
+    /// The `anchor` is the point in each frame that is drawn at the entity's position.
+    pub fn from_spritesheet(sheet: &SpriteSheet, tag: &str, texture_id: usize, anchor: Point) -> Result<Self, String> {
+        let animation = |direction: &str| Animation::from_spritesheet(sheet, &format!("{}_{}", tag, direction), texture_id, anchor);
+
+        let main_directions = ["up", "down", "left", "right"];
+        if main_directions.iter().all(|&direction| animation(direction).is_none()) {
+            let animation = Animation::from_spritesheet(sheet, tag, texture_id, anchor)
+                .ok_or_else(|| format!("spritesheet has no '{}' or '{}_up' tags", tag, tag))?;
+            return Ok(Self {
+                up: animation.clone(),
+                down: animation.clone(),
+                left: animation.clone(),
+                right: animation,
+                up_left: None,
+                up_right: None,
+                down_left: None,
+                down_right: None,
+            });
+        }
+
+        let required = |direction| animation(direction)
+            .ok_or_else(|| format!("spritesheet has no '{}_{}' tag", tag, direction));
+        Ok(Self {
+            up: required("up")?,
+            down: required("down")?,
+            left: required("left")?,
+            right: required("right")?,
+            up_left: animation("up_left"),
+            up_right: animation("up_right"),
+            down_left: animation("down_left"),
+            down_right: animation("down_right"),
+        })
+    }
+
+    /// Changes what happens once the last frame of the animation in each direction has been shown
+    pub fn with_playback(mut self, playback: Playback) -> Self {
+        self.for_each_mut(|animation| animation.playback = playback);
+        self
+    }
+
+    /// Makes the animation in each direction advance by distance travelled instead of by time
+    /// (see `Animation::stride`)
+    pub fn with_stride(mut self, stride: Option<f64>) -> Self {
+        self.for_each_mut(|animation| animation.stride = stride);
+        self
+    }
+
+    /// Returns an animation that only shows the first frame of this one in each direction (e.g. to
+    /// stand still without stopping in the middle of a step)
+    pub fn first_frames(&self) -> Self {
+        let mut first_frames = self.clone();
+        first_frames.for_each_mut(|animation| {
+            *animation = Animation::new(vec![animation.frames[0].clone()], Playback::Loop);
+        });
+        first_frames
+    }
+
+    fn for_each_mut(&mut self, mut f: impl FnMut(&mut Animation)) {
+        let Self {up, down, left, right, up_left, up_right, down_left, down_right} = self;
+        for animation in [up, down, left, right] {
+            f(animation);
+        }
+        for animation in [up_left, up_right, down_left, down_right].iter_mut().filter_map(|animation| animation.as_mut()) {
+            f(animation);
         }
     }
 
+    /// Returns the animation for facing in the given direction
+    ///
+    /// Diagonal directions without a dedicated animation use the left or right animation instead,
+    /// since those are the closest of the four main directions.
     pub fn animation_for(&self, direction: Direction) -> &Animation {
         match direction {
-            Direction::Up => &self.walking_up,
-            Direction::Down => &self.walking_down,
-            Direction::Left => &self.walking_left,
-            Direction::Right => &self.walking_right,
+            Direction::Up => &self.up,
+            Direction::Down => &self.down,
+            Direction::Left => &self.left,
+            Direction::Right => &self.right,
+            Direction::UpLeft => self.up_left.as_ref().unwrap_or(&self.left),
+            Direction::UpRight => self.up_right.as_ref().unwrap_or(&self.right),
+            Direction::DownLeft => self.down_left.as_ref().unwrap_or(&self.left),
+            Direction::DownRight => self.down_right.as_ref().unwrap_or(&self.right),
         }
     }
 }
@@ -126,21 +526,52 @@ impl MovementAnimations {
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
 pub struct Player {
-    /// The speed of the player when they are moving
-    pub movement_speed: i32,
+    /// The speed of the player (in pixels per second) when they are moving
+    pub movement_speed: f64,
 }
 
 /// Marks an entity as an enemy that will cause damage to the player
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
 pub struct Enemy {
-    /// The amount of time elapsed since the direction was changed
-    pub direction_timer: Instant,
+    /// The speed of the enemy (in pixels per second)
+    pub movement_speed: f64,
+    /// The game time (from the `GameClock` resource) at which the direction was last changed
+    pub direction_timer: Duration,
     /// The amount of time to wait between direction changes
     pub direction_change_delay: Duration,
 }
 
+/// Marks an entity as solid. Moving entities are not able to pass through its `BoundingBox`.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[storage(NullStorage)]
+pub struct Obstacle;
+
 /// Marks an entity as the goal. If the player reaches this, they win the game.
 #[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
 #[storage(NullStorage)]
 pub struct Goal;
+
+/// Marks an entity as dead. It can no longer move and plays its `Dead` animation, if it has one.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[storage(NullStorage)]
+pub struct Dead;
+
+/// Deletes an entity once its one-shot `Animation` has finished (e.g. for effects like explosions)
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[storage(NullStorage)]
+pub struct DespawnAfterAnimation;
+
+/// Creates a new entity from a prefab every so often, at this entity's `Position`
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct SpawnPoint {
+    /// The name of the prefab to create entities from
+    pub prefab: String,
+    /// The amount of time to wait between creating each entity
+    pub delay: Duration,
+    /// The number of entities left to create
+    pub remaining: usize,
+    /// The game time (from the `GameClock` resource) at which the last entity was created
+    pub timer: Duration,
+}
@@ -1,4 +1,8 @@
-use sdl2::rect::Point;
+use std::f64::consts::FRAC_PI_4;
+
+use rand::Rng;
+
+use crate::vector::Vector;
 
 /// Represents a direction of motion
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -7,17 +11,64 @@ pub enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
-    /// Returns a point that represents this direction in world coordinates
-    pub fn into_point(self) -> Point {
+    /// Returns a vector of length 1 that represents this direction in world coordinates
+    ///
+    /// Diagonal directions are normalized so that moving diagonally is no faster than moving
+    /// along a single axis.
+    pub fn into_vector(self) -> Vector {
+        use Direction::*;
+        let vector = match self {
+            Up => Vector::new(0.0, -1.0),
+            Down => Vector::new(0.0, 1.0),
+            Left => Vector::new(-1.0, 0.0),
+            Right => Vector::new(1.0, 0.0),
+            UpLeft => Vector::new(-1.0, -1.0),
+            UpRight => Vector::new(1.0, -1.0),
+            DownLeft => Vector::new(-1.0, 1.0),
+            DownRight => Vector::new(1.0, 1.0),
+        };
+        vector.normalized()
+    }
+
+    /// Returns the direction closest to the given vector or None if the vector is zero
+    pub fn from_vector(vector: Vector) -> Option<Self> {
+        use Direction::*;
+        if vector.is_zero() {
+            return None;
+        }
+
+        // Divide the circle into 8 equal slices centered on each direction. Angles are measured
+        // clockwise from the positive x-axis since the y-axis points down in world coordinates.
+        let slice = (vector.y.atan2(vector.x) / FRAC_PI_4).round() as i32;
+        Some(match slice.rem_euclid(8) {
+            0 => Right,
+            1 => DownRight,
+            2 => Down,
+            3 => DownLeft,
+            4 => Left,
+            5 => UpLeft,
+            6 => Up,
+            7 => UpRight,
+            _ => unreachable!(),
+        })
+    }
+
+    /// Returns one of the four main directions (up, down, left or right) at random
+    pub fn random(rng: &mut impl Rng) -> Self {
         use Direction::*;
-        match self {
-            Up => Point::new(0, -1),
-            Down => Point::new(0, 1),
-            Left => Point::new(-1, 0),
-            Right => Point::new(1, 0),
+        match rng.gen_range(0, 4) {
+            0 => Up,
+            1 => Down,
+            2 => Left,
+            3 => Right,
+            _ => unreachable!(),
         }
     }
 }
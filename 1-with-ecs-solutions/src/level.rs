@@ -0,0 +1,170 @@
+//! Levels are described by configuration files so that new levels can be made without
+//! recompiling the game. A level file lists the size of the world, an optional Tiled map (see the
+//! `tiled` module) and the entities to spawn from prefabs (see the `prefab` module), e.g.
+//!
+//! ```ron
+//! (
+//!     world_size: (800, 600),
+//!     map: None,
+//!     spawns: [
+//!         (prefab: "tree", position: (-350, 22)),
+//!         // Placed anywhere from x = -300 to x = 300
+//!         (prefab: "pink_tree", position: (0, -142), random_offset: (300, 0)),
+//!         (
+//!             prefab: "reaper",
+//!             position: (0, 90),
+//!             facing: Some(Left),
+//!             // Replaces the Enemy component from the prefab
+//!             components: [Enemy(movement_speed: 100, direction_change_delay: 200)],
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//! Levels with a map leave out `world_size`, since the world is always the size of the map.
+//!
+//! A level can also have its own `textures`, `spritesheets`, `layers` and `prefabs`, in the same
+//! format as `prefabs.ron`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use sdl2::rect::Rect;
+use serde::Deserialize;
+use specs::World;
+
+use crate::replay;
+use crate::tiled::Map;
+use crate::vector::Vector;
+use crate::prefab::{Prefabs, Prefab, Overrides, Facing, ComponentDef};
+
+/// A level loaded from a level file
+#[derive(Debug, Deserialize)]
+pub struct Level {
+    /// The width and height of the world in pixels. The world is centered on (0, 0). Only given
+    /// for levels without a map.
+    #[serde(default)]
+    world_size: Option<(u32, u32)>,
+    /// The path of the Tiled map to draw beneath the entities
+    #[serde(default, rename = "map")]
+    map_path: Option<PathBuf>,
+    /// The map loaded from `map_path`
+    #[serde(skip)]
+    map: Option<Map>,
+    /// The path of every texture used only by this level, by name
+    #[serde(default)]
+    textures: BTreeMap<String, PathBuf>,
+    /// The path of every spritesheet used only by this level, by name
+    #[serde(default)]
+    spritesheets: BTreeMap<String, PathBuf>,
+    /// The name of every collision layer used only by this level
+    #[serde(default)]
+    layers: Vec<String>,
+    /// Prefabs used only by this level, by name
+    #[serde(default)]
+    prefabs: BTreeMap<String, Prefab>,
+    #[serde(default)]
+    spawns: Vec<SpawnDef>,
+    /// A checksum of the level file (see `replay::file_checksum`)
+    #[serde(skip)]
+    checksum: u64,
+}
+
+/// An entity to create when the level starts
+#[derive(Debug, Deserialize)]
+struct SpawnDef {
+    /// The name of the prefab to create the entity from
+    prefab: String,
+    /// The position (in world coordinates) where the entity touches the ground
+    position: (f64, f64),
+    /// The position is moved a random whole number of pixels, up to this far in each direction
+    #[serde(default)]
+    random_offset: (u32, u32),
+    /// Replaces the direction from the prefab
+    #[serde(default)]
+    facing: Option<Facing>,
+    /// Replaces the components of the same kind from the prefab
+    #[serde(default)]
+    components: Vec<ComponentDef>,
+}
+
+impl Level {
+    /// Loads a level from the given file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)
+            .map_err(|err| format!("unable to read level '{}': {}", path.display(), err))?;
+        let mut level: Self = ron::de::from_str(&config)
+            .map_err(|err| format!("invalid level '{}': {}", path.display(), err))?;
+        level.checksum = replay::file_checksum(config.as_bytes());
+
+        match (&level.map_path, level.world_size) {
+            (Some(_), Some(_)) => {
+                return Err(format!("invalid level '{}': world_size must be left out since the world \
+                    is the size of the map", path.display()));
+            },
+            (Some(map_path), None) => level.map = Some(Map::load(map_path)?),
+            (None, Some((width, height))) => if width == 0 || height == 0 {
+                return Err(format!("invalid level '{}': world_size must not be zero", path.display()));
+            },
+            (None, None) => {
+                return Err(format!("invalid level '{}': world_size is required for levels without a map", path.display()));
+            },
+        }
+
+        Ok(level)
+    }
+
+    /// The area covered by the world in world coordinates
+    pub fn world_bounds(&self) -> Rect {
+        match (&self.map, self.world_size) {
+            (Some(map), _) => map.bounds(),
+            (None, Some((width, height))) => Rect::from_center((0, 0), width, height),
+            (None, None) => unreachable!("bug: levels without a map should have a world size"),
+        }
+    }
+
+    /// A checksum of the level file, used to check that a replay is played back with the level
+    /// it was recorded with
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// The Tiled map to draw beneath the entities, if any
+    pub fn map(&self) -> Option<&Map> {
+        self.map.as_ref()
+    }
+
+    /// Adds the prefabs, textures, spritesheets and collision layers used only by this level
+    pub fn add_prefabs(&self, prefabs: &mut Prefabs) -> Result<(), String> {
+        prefabs.extend(&self.textures, &self.spritesheets, &self.layers, &self.prefabs)
+            .map_err(|err| format!("invalid level: {}", err))
+    }
+
+    /// Creates every entity listed in the level
+    pub fn spawn(&self, world: &mut World, prefabs: &Prefabs, rng: &mut impl Rng) -> Result<(), String> {
+        for (index, spawn) in self.spawns.iter().enumerate() {
+            let (x, y) = spawn.position;
+            let (offset_x, offset_y) = spawn.random_offset;
+            let mut position = Vector::new(x, y);
+            if offset_x != 0 {
+                position.x += rng.gen_range(-(offset_x as i32), offset_x as i32 + 1) as f64;
+            }
+            if offset_y != 0 {
+                position.y += rng.gen_range(-(offset_y as i32), offset_y as i32 + 1) as f64;
+            }
+
+            let overrides = Overrides {
+                position,
+                facing: spawn.facing,
+                components: spawn.components.clone(),
+            };
+            prefabs.spawn(world, &spawn.prefab, &overrides, rng)
+                .map_err(|err| format!("invalid level: spawn {}: {}", index, err))?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,37 +1,82 @@
+mod vector;
 mod direction;
+mod action;
+mod bindings;
 mod components;
 mod resources;
 mod systems;
+mod spatial_hash;
+mod camera;
+mod tiled;
+mod spritesheet;
+mod prefab;
+mod level;
+mod world_canvas;
 mod renderer;
+mod simulation;
+mod replay;
 
+use std::env;
 use std::thread;
 use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::time::{Instant, Duration};
 
 use rand::{Rng, thread_rng};
 use sdl2::{
     event::Event,
-    keyboard::Keycode,
     pixels::Color,
-    rect::{Point, Rect},
     image::{self, LoadTexture, InitFlag},
 };
-use specs::{World, WorldExt, Builder, DispatcherBuilder, SystemData};
-
-use crate::direction::Direction;
-use crate::resources::{TimeDelta, KeyboardEvent, GameStatus};
-use crate::components::{
-    BoundingBox,
-    Velocity,
-    Sprite,
-    MovementAnimations,
-    Player,
-    Enemy,
-    Goal,
-};
+use specs::{World, WorldExt, Entity, Join, SystemData};
+
+use crate::vector::Vector;
+use crate::action::Action;
+use crate::bindings::Bindings;
+use crate::resources::{HeldActions, GameStatus, GameRng, TimeScale};
+use crate::components::{Position, Player};
+use crate::camera::Camera;
+use crate::tiled::{Map, SOLID_PREFAB};
+use crate::prefab::{Prefabs, Overrides, ComponentDef};
+use crate::level::Level;
 use crate::renderer::RendererData;
+use crate::simulation::Simulation;
+use crate::replay::{Recorder, Replay, StepInput};
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args()?;
+
+    // When playing back a replay, the input comes from the replay file instead of the keyboard
+    let mut replay = match &options.replay {
+        Some(path) => Some(Replay::load(path)?),
+        None => None,
+    };
+
+    // All randomness in the game is derived from this seed. Printing it means that any run of the
+    // game can be reproduced exactly by passing the same seed back in with `--seed`.
+    let seed = match (&replay, options.seed) {
+        (Some(replay), _) => replay.seed(),
+        (None, Some(seed)) => seed,
+        (None, None) => thread_rng().gen(),
+    };
+    println!("Using seed: {}", seed);
+
+    // Load the configurable mapping from keys to actions
+    let mut bindings = Bindings::load("bindings.ron")?;
+
+    // Load the level to play (along with the map drawn beneath it, if any) and the prefabs its
+    // entities are made from
+    let level = Level::load(&options.level)?;
+    let mut prefabs = Prefabs::load("prefabs.ron")?;
+    level.add_prefabs(&mut prefabs)?;
+    if let Some(replay) = &replay {
+        if replay.level_checksum() != level.checksum() {
+            return Err(format!("the replay was recorded with a different level than '{}', \
+                pass the level it was recorded with to --level", options.level.display()).into());
+        }
+    }
+    let map = level.map();
+
     // Initialize the SDL2 library
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
@@ -47,200 +92,310 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Create a canvas that draws on the window
     let mut canvas = window.into_canvas().build()?;
-    // The boundary of the window in world coordinates
-    let world_bounds = {
-        let (width, height) = canvas.output_size()?;
-        Rect::from_center((0, 0), width, height)
+    // The boundary of the level in world coordinates. This doesn't need to match the size of the
+    // window since the camera scrolls around to show whichever part of the level is needed.
+    let world_bounds = match &replay {
+        // Use the same bounds as the recording
+        Some(replay) => replay.world_bounds(),
+        None => level.world_bounds(),
     };
 
     // Load assets
     let texture_creator = canvas.texture_creator();
-    // Store the textures in an array so that they can be referenced by index. This allows textures
+    // Store the textures in a list so that they can be referenced by index. This allows textures
     // to be shared between entities without having to copy the texture all over the place.
-    let textures = [
-        texture_creator.load_texture("assets/bardo_2x.png")?,
-        texture_creator.load_texture("assets/reaper_blade_2x.png")?,
-        texture_creator.load_texture("assets/pinktrees_2x.png")?,
-    ];
-    let bardo_texture = 0;
-    let reaper_texture = 1;
-    let pink_trees_texture = 2;
-
-    // Declare the hierarchy of systems that will process entities and components
-    let mut dispatcher = DispatcherBuilder::new()
-        .with(systems::Keyboard, "Keyboard", &[])
-        .with(systems::AI, "AI", &[])
-        .with(systems::Movement {world_bounds}, "Movement", &["Keyboard", "AI"])
-        .with(systems::WinLoseChecker, "WinLoseChecker", &["Movement"])
-        .with(systems::Animator, "Animator", &["Keyboard", "AI"])
-        .build();
+    let load_texture = |path: &Path| texture_creator.load_texture(path)
+        .map_err(|err| format!("unable to load texture '{}': {}", path.display(), err));
+    let mut textures = prefabs.texture_paths().map(load_texture).collect::<Result<Vec<_>, _>>()?;
+    // The tilesets of the map are loaded after the textures above
+    let first_tileset_texture = textures.len();
+    if let Some(map) = map {
+        for image in map.tileset_images() {
+            textures.push(load_texture(image)?);
+        }
+    }
+
+    // The amount of game time simulated by each step of the simulation. Using a fixed step keeps
+    // the simulation consistent no matter how quickly or slowly frames are actually drawn.
+    let time_step = Duration::from_nanos(1_000_000_000 / 60);
 
     // Game state
-    let mut world = World::new();
-    // Setup the component storages based on the data used by the systems
-    dispatcher.setup(&mut world);
-    RendererData::setup(&mut world);
-
-    let mut rng = thread_rng();
-
-    world.create_entity()
-        .with(Goal)
-        .with(BoundingBox(Rect::from_center((rng.gen_range(-300, 301), -200), 92, 116)))
-        .with(Sprite {
-            texture_id: pink_trees_texture,
-            region: Rect::new(0, 0, 128, 128),
-        })
-        .build();
-
-    let player_animations = MovementAnimations::standard_walking_animations(
-        bardo_texture,
-        Rect::new(0, 0, 52, 72),
-        3,
-        Duration::from_millis(150),
-    );
-
-    world.create_entity()
-        .with(Player {movement_speed: 200})
-        .with(BoundingBox(Rect::from_center((rng.gen_range(-320, 321), 250), 32, 58)))
-        .with(Velocity {speed: 0, direction: Direction::Down})
-        .with(player_animations.animation_for(Direction::Down).frames[0].sprite.clone())
-        .with(player_animations.animation_for(Direction::Down).clone())
-        .with(player_animations)
-        .build();
-
-    // Generate enemies in random positions. To avoid overlap with anything else, an area of the
-    // world coordinate system is divided up into a 2D grid. Each enemy gets a random position
-    // within one of the cells of that grid.
-    let enemy_animations = MovementAnimations::standard_walking_animations(
-        reaper_texture,
-        Rect::new(0, 0, 64, 72),
-        3,
-        Duration::from_millis(150),
-    );
-
-    for i in -1..2 {
-        for j in -2..0 {
-            let enemy_pos = Point::new(
-                i * 200 + rng.gen_range(-80, 80),
-                j * 140 + 200 + rng.gen_range(-40, 40),
-            );
-            let enemy_dir = match rng.gen_range(0, 4) {
-                0 => Direction::Up,
-                1 => Direction::Down,
-                2 => Direction::Left,
-                3 => Direction::Right,
-                _ => unreachable!(),
-            };
+    let mut simulation = Simulation::new(world_bounds, time_step, seed);
+    let world = simulation.world_mut();
+    // Setup the component storages used by the renderer (the simulation sets up everything else)
+    RendererData::setup(world);
 
-            world.create_entity()
-                .with(Enemy {
-                    direction_timer: Instant::now(),
-                    direction_change_delay: Duration::from_millis(200),
-                })
-                .with(BoundingBox(Rect::from_center(enemy_pos, 50, 58)))
-                .with(Velocity {speed: 200, direction: enemy_dir})
-                .with(enemy_animations.animation_for(enemy_dir).frames[0].sprite.clone())
-                .with(enemy_animations.animation_for(enemy_dir).clone())
-                .with(enemy_animations.clone())
-                .build();
-        }
+    // Take the seeded random number generator out of the world while we create entities so that
+    // spawn positions are reproducible too. It gets put back once we're done.
+    let GameRng(mut rng) = world.remove().expect("bug: random number generator not initialized");
+
+    level.spawn(world, &prefabs, &mut rng)?;
+    if let Some(map) = map {
+        world.insert(map.tile_map(first_tileset_texture));
+        spawn_map(world, &prefabs, map, &mut rng)?;
     }
 
-    // Add resources (resources used with ReadExpect/WriteExpect must be added before use)
-    world.insert(TimeDelta::default());
-    world.insert(GameStatus::Running);
+    world.insert(GameRng(rng));
+    // Allow entities to be created from prefabs while the game is running
+    world.insert(prefabs);
+
+    // Look at the level through a camera that follows the player around
+    let player = find_player(world)?;
+    let mut camera = Camera::new(world_bounds, canvas.output_size()?);
+    camera.target = Some(player);
+    let player_pos = world.read_storage::<Position>().get(player)
+        .expect("bug: player should have a position").0;
+    camera.jump_to(player_pos);
+    world.insert(camera);
+
+    // Record every step of the session if requested
+    let mut recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path, seed, level.checksum(), world_bounds)?),
+        None => None,
+    };
 
     // Begin game loop
-    let frame_duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    // The maximum number of steps we will simulate to catch up before drawing the next frame.
+    // Without this, a long stall (e.g. dragging the window) would make us try to simulate so much
+    // time at once that we would fall even further behind.
+    let max_steps_per_frame = 5;
+
+    // Real time that has passed but has not been simulated yet
+    let mut accumulator = Duration::from_secs(0);
+    let mut last_frame = Instant::now();
+    // The actions currently being held down by the player
+    let mut held_actions = HeldActions::default();
+
     let mut event_pump = sdl_context.event_pump()?;
     // A labelled loop can be used with `break` even from inside another loop
     'running: loop {
         // HANDLE EVENTS
 
         // Handle all of the events available right now
-        let mut keyboard_event = None;
         for event in event_pump.poll_iter() {
             match event {
-                // Quit the game if the window is closed or if the escape key is pressed
-                Event::Quit {..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'running
+                // Quit the game if the window is closed
+                Event::Quit {..} => break 'running,
+                // Translate keys into actions using the configured key bindings. Keeping track of
+                // all the held actions lets the player move in the direction of the most recently
+                // pressed key that is still held.
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    let action = match bindings.key_down(keycode) {
+                        Some(action) => action,
+                        None => continue,
+                    };
+                    held_actions.press(action);
+
+                    // Actions that control the game itself are handled right away
+                    let mut time_scale = simulation.world().write_resource::<TimeScale>();
+                    match action {
+                        Action::Quit => break 'running,
+                        // Debug controls for pausing, slowing down or speeding up the game
+                        Action::Pause => time_scale.paused = !time_scale.paused,
+                        // Only has an effect while the game is paused
+                        Action::StepOnce => time_scale.step_once = true,
+                        Action::QuarterSpeed => time_scale.speed = 0.25,
+                        Action::HalfSpeed => time_scale.speed = 0.5,
+                        Action::NormalSpeed => time_scale.speed = 1.0,
+                        Action::DoubleSpeed => time_scale.speed = 2.0,
+                        Action::ZoomIn => simulation.world().write_resource::<Camera>().zoom_by(1.25),
+                        Action::ZoomOut => simulation.world().write_resource::<Camera>().zoom_by(0.8),
+                        // Handled by the systems
+                        Action::MoveUp | Action::MoveDown | Action::MoveLeft | Action::MoveRight => {},
+                    }
                 },
-                // Set the player direction and speed based on the arrow key that is pressed
-                Event::KeyDown { keycode: Some(Keycode::Up), repeat: false, .. } => {
-                    keyboard_event = Some(KeyboardEvent::MoveInDirection(Direction::Up));
+                Event::KeyUp { keycode: Some(keycode), repeat: false, .. } => {
+                    if let Some(action) = bindings.key_up(keycode) {
+                        held_actions.release(action);
+                    }
                 },
-                Event::KeyDown { keycode: Some(Keycode::Down), repeat: false, .. } => {
-                    keyboard_event = Some(KeyboardEvent::MoveInDirection(Direction::Down));
+                _ => {}
+            }
+        }
+
+        // UPDATE
+
+        // Measure how much real time has passed since the last frame and add it to the time that
+        // still needs to be simulated. The amount added is capped so that we never try to run
+        // more than `max_steps_per_frame` steps at once.
+        let now = Instant::now();
+        accumulator += (now - last_frame).min(time_step * max_steps_per_frame);
+        last_frame = now;
+
+        // Run as many fixed steps as are needed to catch up to the current time. This can be
+        // zero steps if the frame was drawn very quickly.
+        while accumulator >= time_step {
+            // Figure out the input for this step
+            let input = match &replay {
+                Some(replay) => match replay.next_input() {
+                    Some(input) => input.clone(),
+                    None => {
+                        println!("Replay finished");
+                        break 'running;
+                    },
                 },
-                Event::KeyDown { keycode: Some(Keycode::Left), repeat: false, .. } => {
-                    keyboard_event = Some(KeyboardEvent::MoveInDirection(Direction::Left));
+                None => StepInput {
+                    held_actions: held_actions.clone(),
+                    time_scale: *simulation.world().read_resource(),
                 },
-                Event::KeyDown { keycode: Some(Keycode::Right), repeat: false, .. } => {
-                    keyboard_event = Some(KeyboardEvent::MoveInDirection(Direction::Right));
+            };
+
+            // Update game state
+            *simulation.world().write_resource() = input.time_scale;
+            simulation.step(&input.held_actions);
+            accumulator -= time_step;
+
+            if let Some(recorder) = &mut recorder {
+                recorder.record_step(&input, simulation.world())?;
+            }
+            if let Some(replay) = &mut replay {
+                replay.verify_step(simulation.world())?;
+            }
+
+            // Check if we need to quit the game
+            match simulation.status() {
+                GameStatus::Running => {}, // Keep going
+                GameStatus::Win => {
+                    println!("You win!");
+                    break 'running;
                 },
-                Event::KeyUp { keycode: Some(Keycode::Left), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Right), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Up), repeat: false, .. } |
-                Event::KeyUp { keycode: Some(Keycode::Down), repeat: false, .. } => {
-                    keyboard_event = Some(KeyboardEvent::Stop);
+                GameStatus::Lose => {
+                    println!("You lose!");
+                    break 'running;
                 },
-                _ => {}
             }
         }
-        // Inform the systems of the keyboard event
-        world.insert(keyboard_event);
 
-        // UPDATE
+        // RENDER
 
-        // Store the time elapsed since the last frame in a resource so that all systems may have
-        // access to it. This is mostly just done for illustration purposes since we technically
-        // do not need to repeatedly set the value over and over again if it is constant.
-        *world.write_resource() = TimeDelta(frame_duration);
-
-        // Update game state
-        dispatcher.dispatch(&world);
-        // Apply any lazy updates that occurred during dispatch
-        world.maintain();
-
-        // Check if we need to quit the game
-        match *world.read_resource() {
-            GameStatus::Running => {}, // Keep going
-            GameStatus::Win => {
-                println!("You win!");
-                break;
-            },
-            GameStatus::Lose => {
-                println!("You lose!");
-                break;
-            },
+        // Show the time scale in the window title so that it is clear when the game is paused or
+        // running at a different speed. Drawing text in the game itself would need SDL2_ttf, so
+        // this isn't visible in fullscreen or with window managers that hide the title.
+        let title = window_title(&simulation.world().read_resource());
+        if canvas.window().title() != title {
+            canvas.window_mut().set_title(&title)?;
         }
 
-        // RENDER
+        // How far we are between the last step and the next one. Used to smooth out the motion
+        // of entities since frames won't always line up exactly with steps.
+        let alpha = accumulator.as_secs_f64() / time_step.as_secs_f64();
 
         // Draw the game onto the screen
         canvas.set_draw_color(Color::RGB(128, 128, 128));
         canvas.clear();
 
-        let renderer_data: RendererData = world.system_data();
-        renderer_data.render(&mut canvas, &textures)?;
+        let renderer_data: RendererData = simulation.world().system_data();
+        renderer_data.render(&mut canvas, &textures, alpha)?;
 
         canvas.present();
 
         // LIMIT FRAMERATE
 
-        // Manage the timing of the game so that the loop doesn't go too quickly or too slowly.
+        // There is no point drawing frames faster than we simulate them, so sleep until the next
+        // step is due. Sleeping is not guaranteed to be precise, but that's fine since any extra
+        // time will just be picked up by the accumulator.
         //
-        // Time stepping is a complex topic. We're simplifying things by just always assuming that
-        // 1/60 seconds has passed in each iteration of the loop. 1/60th of a second is 60 FPS.
-        // There are *many* downsides to the code as it is below, but it's good enough as a
-        // starting point.
-        //
-        // For more information and some more robust approaches:
+        // For more information about this approach and time stepping in general:
         // * http://web.archive.org/web/20190506122532/http://gafferongames.com/post/fix_your_timestep/
         // * https://www.gamasutra.com/blogs/BramStolk/20160408/269988/Fixing_your_time_step_the_easy_way_with_the_golden_48537_ms.php
-        thread::sleep(frame_duration);
+        let time_to_next_step = time_step - accumulator;
+        thread::sleep(time_to_next_step.saturating_sub(last_frame.elapsed()));
     }
 
     Ok(())
 }
+
+/// Creates the entities placed in the map and makes its solid tiles block movement
+fn spawn_map(world: &mut World, prefabs: &Prefabs, map: &Map, rng: &mut impl Rng) -> Result<(), String> {
+    for spawn in map.spawns() {
+        let overrides = Overrides {position: spawn.position, ..Overrides::default()};
+        prefabs.spawn(world, &spawn.prefab, &overrides, rng)
+            .map_err(|err| format!("invalid map: object {}: {}", spawn.object_id, err))?;
+    }
+
+    // Each solid area is covered by an entity made from the same prefab, sized to fit the area
+    let solid_areas = map.solid_areas();
+    if !solid_areas.is_empty() && !prefabs.contains(SOLID_PREFAB) {
+        return Err(format!("invalid map: has solid tiles but there is no '{}' prefab to cover them with", SOLID_PREFAB));
+    }
+    for area in solid_areas {
+        let height = area.height() as f64;
+        let overrides = Overrides {
+            position: Vector::new(area.center().x() as f64, area.bottom() as f64),
+            components: vec![ComponentDef::Collider {
+                width: area.width(),
+                height: area.height(),
+                offset: (0.0, -height / 2.0),
+            }],
+            ..Overrides::default()
+        };
+        prefabs.spawn(world, SOLID_PREFAB, &overrides, rng)
+            .map_err(|err| format!("invalid map: solid tiles: {}", err))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the keyboard controlled player, which every level must have exactly one of
+fn find_player(world: &World) -> Result<Entity, String> {
+    let entities = world.entities();
+    let players = world.read_storage::<Player>();
+    let mut players = (&entities, &players).join().map(|(entity, _)| entity);
+
+    match (players.next(), players.next()) {
+        (Some(player), None) => Ok(player),
+        (None, _) => Err("the level has no player".to_string()),
+        (Some(_), Some(_)) => Err("the level has more than one player".to_string()),
+    }
+}
+
+/// Returns the title of the window based on the current time scale
+fn window_title(time_scale: &TimeScale) -> String {
+    let mut title = "Minimal Game".to_string();
+    if time_scale.paused {
+        title += " [Paused]";
+    }
+    if time_scale.speed != 1.0 {
+        title += &format!(" [{}x]", time_scale.speed);
+    }
+    title
+}
+
+/// Options that can be passed to the game on the command line
+#[derive(Debug, Default)]
+struct Options {
+    /// `--seed <seed>`: the seed to use for all randomness in the game
+    seed: Option<u64>,
+    /// `--record <path>`: save a replay of the session to the given file
+    record: Option<PathBuf>,
+    /// `--replay <path>`: play back the replay in the given file instead of taking input from
+    /// the keyboard
+    replay: Option<PathBuf>,
+    /// `--level <path>`: the level file to play (defaults to `levels/default.ron`). A replay must
+    /// be played back with the same level that it was recorded with.
+    level: PathBuf,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut options = Self {level: "levels/default.ron".into(), ..Self::default()};
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("expected a value after {}", arg));
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(seed.parse().map_err(|err| format!("invalid seed '{}': {}", seed, err))?);
+                },
+                "--record" => options.record = Some(value()?.into()),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--level" => options.level = value()?.into(),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,560 @@
+//! Prefabs are named templates for entities, described in configuration files so that every
+//! entity of the same kind is created the same way. The prefabs shared by every level are in
+//! `prefabs.ron`, and each level can add its own, e.g.
+//!
+//! ```ron
+//! (
+//!     textures: {
+//!         "trees": "assets/trees_2x.png",
+//!     },
+//!     prefabs: {
+//!         "tree": (
+//!             components: [
+//!                 Obstacle,
+//!                 Collider(width: 80, height: 104, offset: (0, -52)),
+//!                 CollisionFilter(layers: ["Obstacle"], mask: ["All"]),
+//!                 Sprite(texture: "trees", region: (0, 0, 96, 128), anchor: (48, 120)),
+//!             ],
+//!         ),
+//!     },
+//! )
+//! ```
+//!
+//! A prefab is instantiated with `Overrides` for anything that differs between entities made
+//! from it, such as their position. See `prefabs.ron` for every kind of component that can be
+//! given to a prefab.
+
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+
+use rand::Rng;
+use sdl2::rect::{Point, Rect};
+use serde::Deserialize;
+use specs::{World, WorldExt, Builder, Entity, Entities, LazyUpdate};
+
+use crate::vector::Vector;
+use crate::direction::Direction;
+use crate::spritesheet::SpriteSheet;
+use crate::components::{
+    Position,
+    Collider,
+    CollisionFilter,
+    Layers,
+    BoundingBox,
+    Velocity,
+    Sprite,
+    Animation,
+    AnimationStates,
+    AnimationState,
+    DirectionalAnimation,
+    DelayedAnimation,
+    Player,
+    Enemy,
+    Goal,
+    Obstacle,
+    Playback,
+    DespawnAfterAnimation,
+    SpawnPoint,
+};
+use crate::resources::GameClock;
+
+/// The name of the collision layer that means every layer
+const ALL_LAYERS: &str = "All";
+
+/// Every prefab that can be instantiated, along with the textures they use
+///
+/// Also available as a resource so that systems can create entities while the game is running.
+#[derive(Debug, Default)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+    /// The path of every texture, in the order they must be loaded so that their index in this
+    /// list is their texture ID
+    texture_paths: Vec<PathBuf>,
+    texture_ids: HashMap<String, usize>,
+    /// Each spritesheet has a texture with the same name
+    spritesheets: HashMap<String, SpriteSheet>,
+    /// The collision layer with each name
+    layers: HashMap<String, Layers>,
+}
+
+/// The template for a kind of entity
+#[derive(Debug, Clone, Deserialize)]
+pub struct Prefab {
+    /// The direction that entities made from this prefab start out facing, unless overridden
+    #[serde(default)]
+    facing: Facing,
+    components: Vec<ComponentDef>,
+}
+
+/// The parts of an entity that differ from the prefab it is made from
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// The position (in world coordinates) where the entity touches the ground
+    pub position: Vector,
+    /// Replaces the direction from the prefab
+    pub facing: Option<Facing>,
+    /// Each of these replaces the component of the same kind from the prefab, or is added to the
+    /// entity if the prefab doesn't have one
+    pub components: Vec<ComponentDef>,
+}
+
+/// The direction that an entity starts out facing (and moving in, if it has a `Velocity`)
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub enum Facing {
+    Up,
+    #[default]
+    Down,
+    Left,
+    Right,
+    /// One of the four other directions, chosen randomly
+    Random,
+}
+
+/// A component of an entity, as written in a configuration file
+///
+/// Durations are given in milliseconds and rectangles as `(x, y, width, height)`.
+#[derive(Debug, Clone, Deserialize)]
+pub enum ComponentDef {
+    Player {
+        movement_speed: f64,
+    },
+    Enemy {
+        movement_speed: f64,
+        direction_change_delay: u64,
+    },
+    Goal,
+    Obstacle,
+    /// Starts the entity moving in the direction it is facing at the given speed
+    Velocity {
+        speed: f64,
+    },
+    /// Also gives the entity a `BoundingBox`
+    Collider {
+        width: u32,
+        height: u32,
+        offset: (f64, f64),
+    },
+    /// Layers are given by name, where `All` means every layer
+    CollisionFilter {
+        layers: Vec<String>,
+        mask: Vec<String>,
+    },
+    Sprite {
+        texture: String,
+        region: (i32, i32, u32, u32),
+        anchor: (i32, i32),
+    },
+    /// Walking animations laid out using the standard convention for spritesheets (see
+    /// `DirectionalAnimation::standard_walking_animations`). Gives the entity `AnimationStates`
+    /// that stand still on the first frame of walking, along with the `Sprite` and `Animation` for
+    /// the direction it is facing.
+    WalkingAnimations {
+        texture: String,
+        /// The position and size of the top-left frame
+        frame: (i32, i32, u32, u32),
+        anchor: (i32, i32),
+        frames: usize,
+        frame_duration: u64,
+        /// If set, walking advances a frame each time the entity travels this many pixels instead
+        /// of after each `frame_duration`
+        #[serde(default)]
+        stride: Option<f64>,
+    },
+    /// The animation for each state of the entity from a spritesheet (see
+    /// `DirectionalAnimation::from_spritesheet` for how the tags are named). There must be one for
+    /// `Walk`. Also gives the entity the `Sprite` and `Animation` for the direction it is facing.
+    AnimationStates {
+        sheet: String,
+        anchor: (i32, i32),
+        states: BTreeMap<AnimationState, ClipDef>,
+        /// Played after the entity has been idle for a while
+        #[serde(default)]
+        idle_animation: Option<DelayedClipDef>,
+    },
+    /// An animation from a spritesheet. Also gives the entity the `Sprite` for its first frame.
+    Animation {
+        sheet: String,
+        tag: String,
+        anchor: (i32, i32),
+        #[serde(default)]
+        playback: Playback,
+    },
+    /// Deletes the entity once its one-shot animation has finished
+    DespawnAfterAnimation,
+    SpawnPoint {
+        prefab: String,
+        delay: u64,
+        count: usize,
+    },
+}
+
+/// An animation from a spritesheet
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClipDef {
+    tag: String,
+    #[serde(default)]
+    playback: Playback,
+    /// If set, the animation advances a frame each time the entity travels this many pixels
+    /// instead of after each frame's duration
+    #[serde(default)]
+    stride: Option<f64>,
+}
+
+/// An animation from a spritesheet that is only played after some time has passed
+#[derive(Debug, Clone, Deserialize)]
+pub struct DelayedClipDef {
+    tag: String,
+    #[serde(default)]
+    playback: Playback,
+    delay: u64,
+}
+
+/// The contents of `prefabs.ron`
+#[derive(Debug, Deserialize)]
+struct PrefabsFile {
+    #[serde(default)]
+    textures: BTreeMap<String, PathBuf>,
+    /// The path of each spritesheet's JSON file
+    #[serde(default)]
+    spritesheets: BTreeMap<String, PathBuf>,
+    /// The name of each collision layer
+    #[serde(default)]
+    layers: Vec<String>,
+    prefabs: BTreeMap<String, Prefab>,
+}
+
+impl Prefabs {
+    /// Loads the prefabs and textures from the given file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)
+            .map_err(|err| format!("unable to read prefabs from '{}': {}", path.display(), err))?;
+        let PrefabsFile {textures, spritesheets, layers, prefabs} = ron::de::from_str(&config)
+            .map_err(|err| format!("invalid prefabs in '{}': {}", path.display(), err))?;
+
+        let mut all_prefabs = Self::default();
+        all_prefabs.extend(&textures, &spritesheets, &layers, &prefabs)
+            .map_err(|err| format!("invalid prefabs in '{}': {}", path.display(), err))?;
+        Ok(all_prefabs)
+    }
+
+    /// Adds more prefabs and the textures, spritesheets and collision layers they use
+    ///
+    /// The new prefabs can use any texture, spritesheet, layer or prefab that has already been
+    /// added. Names that are already taken cannot be reused.
+    pub fn extend(
+        &mut self,
+        textures: &BTreeMap<String, PathBuf>,
+        spritesheets: &BTreeMap<String, PathBuf>,
+        layers: &[String],
+        prefabs: &BTreeMap<String, Prefab>,
+    ) -> Result<(), String> {
+        for (name, path) in textures {
+            self.add_texture(name, path.clone())?;
+        }
+
+        for (name, path) in spritesheets {
+            let sheet = SpriteSheet::load(path)?;
+            self.add_texture(name, sheet.image().to_path_buf())?;
+            self.spritesheets.insert(name.clone(), sheet);
+        }
+
+        for name in layers {
+            if name == ALL_LAYERS || self.layers.contains_key(name) {
+                return Err(format!("there is already a collision layer named '{}'", name));
+            }
+            if self.layers.len() == Layers::COUNT {
+                return Err(format!("there can be at most {} collision layers", Layers::COUNT));
+            }
+            self.layers.insert(name.clone(), Layers::single(self.layers.len()));
+        }
+
+        for (name, prefab) in prefabs {
+            if self.prefabs.contains_key(name) {
+                return Err(format!("there is already a prefab named '{}'", name));
+            }
+            self.prefabs.insert(name.clone(), prefab.clone());
+        }
+
+        // Prefabs may refer to each other, so they can only be checked once they have all been added
+        for (name, prefab) in prefabs {
+            self.check_components(&prefab.components)
+                .map_err(|err| format!("prefab '{}' {}", name, err))?;
+        }
+
+        Ok(())
+    }
+
+    fn add_texture(&mut self, name: &str, path: PathBuf) -> Result<(), String> {
+        if self.texture_ids.contains_key(name) {
+            return Err(format!("there is already a texture or spritesheet named '{}'", name));
+        }
+        self.texture_ids.insert(name.to_string(), self.texture_paths.len());
+        self.texture_paths.push(path);
+        Ok(())
+    }
+
+    /// The path of every texture used by the prefabs
+    ///
+    /// These need to be loaded in this order so that the texture IDs of the entities created from
+    /// the prefabs refer to the right textures.
+    pub fn texture_paths(&self) -> impl Iterator<Item=&Path> {
+        self.texture_paths.iter().map(|path| path.as_path())
+    }
+
+    /// Creates an entity from the prefab with the given name
+    pub fn spawn(&self, world: &mut World, name: &str, overrides: &Overrides, rng: &mut impl Rng) -> Result<Entity, String> {
+        let components = self.components(name, overrides)?;
+        let direction = self.direction(name, overrides, rng);
+        let now = world.read_resource::<GameClock>().now();
+        Ok(build(self, world.create_entity(), &components, overrides.position, direction, now).build())
+    }
+
+    /// Creates an entity from the prefab with the given name the next time the world is maintained
+    ///
+    /// This allows systems to create entities while the game is running. Any animations start at
+    /// the game time `now`.
+    pub fn spawn_lazy(
+        &self,
+        entities: &Entities,
+        lazy: &LazyUpdate,
+        name: &str,
+        overrides: &Overrides,
+        now: Duration,
+        rng: &mut impl Rng,
+    ) -> Result<Entity, String> {
+        let components = self.components(name, overrides)?;
+        let direction = self.direction(name, overrides, rng);
+        Ok(build(self, lazy.create_entity(entities), &components, overrides.position, direction, now).build())
+    }
+
+    /// Returns true if there is a prefab with the given name
+    pub fn contains(&self, name: &str) -> bool {
+        self.prefabs.contains_key(name)
+    }
+
+    /// Returns the components of the given prefab with the overrides applied to them
+    fn components(&self, name: &str, overrides: &Overrides) -> Result<Vec<ComponentDef>, String> {
+        let prefab = self.prefabs.get(name).ok_or_else(|| format!("unknown prefab '{}'", name))?;
+        self.check_components(&overrides.components)
+            .map_err(|err| format!("overrides for prefab '{}' {}", name, err))?;
+
+        let mut components = prefab.components.clone();
+        for component in &overrides.components {
+            let same_kind = components.iter_mut()
+                .find(|other| mem::discriminant(*other) == mem::discriminant(component));
+            match same_kind {
+                Some(other) => *other = component.clone(),
+                None => components.push(component.clone()),
+            }
+        }
+
+        Ok(components)
+    }
+
+    /// Returns the direction that an entity made from the given prefab should start out facing
+    fn direction(&self, name: &str, overrides: &Overrides, rng: &mut impl Rng) -> Direction {
+        let facing = overrides.facing.unwrap_or(self.prefabs[name].facing);
+        match facing {
+            Facing::Up => Direction::Up,
+            Facing::Down => Direction::Down,
+            Facing::Left => Direction::Left,
+            Facing::Right => Direction::Right,
+            Facing::Random => Direction::random(rng),
+        }
+    }
+
+    /// Combines the named layers into a single set of layers
+    ///
+    /// Every name must have already been checked with `check_components`.
+    fn layers_from_names(&self, names: &[String]) -> Layers {
+        names.iter().fold(Layers::NONE, |layers, name| layers | match name.as_str() {
+            ALL_LAYERS => Layers::ALL,
+            name => self.layers[name],
+        })
+    }
+
+    /// Checks for mistakes in components that can't be caught while parsing a configuration file
+    fn check_components(&self, components: &[ComponentDef]) -> Result<(), String> {
+        let check_texture = |name: &str| match self.texture_ids.contains_key(name) {
+            true => Ok(()),
+            false => Err(format!("uses unknown texture '{}'", name)),
+        };
+
+        // A stride of zero would never move on to the next frame
+        let check_stride = |stride: Option<f64>| match stride {
+            Some(stride) if stride <= 0.0 => Err("has an animation with a stride that isn't positive".to_string()),
+            _ => Ok(()),
+        };
+
+        for component in components {
+            match component {
+                ComponentDef::Collider {width, height, ..} if *width == 0 || *height == 0 => {
+                    return Err("has a Collider with a size of zero".to_string());
+                },
+                ComponentDef::CollisionFilter {layers, mask} => {
+                    let unknown = layers.iter().chain(mask)
+                        .find(|&name| name != ALL_LAYERS && !self.layers.contains_key(name));
+                    if let Some(name) = unknown {
+                        return Err(format!("uses unknown collision layer '{}'", name));
+                    }
+                },
+                ComponentDef::Sprite {texture, region: (_, _, width, height), ..} => {
+                    check_texture(texture)?;
+                    if *width == 0 || *height == 0 {
+                        return Err("has a Sprite with a size of zero".to_string());
+                    }
+                },
+                ComponentDef::WalkingAnimations {texture, frame: (_, _, width, height), frames, stride, ..} => {
+                    check_texture(texture)?;
+                    if *width == 0 || *height == 0 || *frames == 0 {
+                        return Err("has WalkingAnimations without any frames".to_string());
+                    }
+                    check_stride(*stride)?;
+                },
+                ComponentDef::AnimationStates {sheet, states, idle_animation, ..} => {
+                    let spritesheet = self.spritesheets.get(sheet)
+                        .ok_or_else(|| format!("uses unknown spritesheet '{}'", sheet))?;
+                    if !states.contains_key(&AnimationState::Walk) {
+                        return Err("has AnimationStates without a Walk animation".to_string());
+                    }
+
+                    for clip in states.values() {
+                        check_stride(clip.stride)?;
+                    }
+
+                    let tags = states.values().map(|clip| &clip.tag)
+                        .chain(idle_animation.iter().map(|clip| &clip.tag));
+                    for tag in tags {
+                        DirectionalAnimation::from_spritesheet(spritesheet, tag, 0, Point::new(0, 0))
+                            .map_err(|err| format!("uses spritesheet '{}' which {}", sheet, err))?;
+                    }
+                },
+                ComponentDef::Animation {sheet, tag, ..} => {
+                    let spritesheet = self.spritesheets.get(sheet)
+                        .ok_or_else(|| format!("uses unknown spritesheet '{}'", sheet))?;
+                    if spritesheet.animation(tag).is_none() {
+                        return Err(format!("uses spritesheet '{}' which has no '{}' tag", sheet, tag));
+                    }
+                },
+                ComponentDef::SpawnPoint {prefab, ..} if !self.contains(prefab) => {
+                    return Err(format!("has a SpawnPoint for unknown prefab '{}'", prefab));
+                },
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds the given components to an entity that is being built
+///
+/// Every component must have already been checked with `Prefabs::check_components`. Animations
+/// start at the game time `now`.
+fn build<B: Builder>(
+    prefabs: &Prefabs,
+    mut builder: B,
+    components: &[ComponentDef],
+    pos: Vector,
+    direction: Direction,
+    now: Duration,
+) -> B {
+    let texture_id = |name: &str| prefabs.texture_ids[name];
+
+    builder = builder.with(Position(pos));
+    for component in components {
+        builder = match *component {
+            ComponentDef::Player {movement_speed} => builder.with(Player {movement_speed}),
+            ComponentDef::Enemy {movement_speed, direction_change_delay} => builder.with(Enemy {
+                movement_speed,
+                // Walk in the starting direction for a while before turning, even when created
+                // long after the game started
+                direction_timer: now,
+                direction_change_delay: Duration::from_millis(direction_change_delay),
+            }),
+            ComponentDef::Goal => builder.with(Goal),
+            ComponentDef::Obstacle => builder.with(Obstacle),
+            ComponentDef::Velocity {speed} => builder.with(Velocity(direction.into_vector() * speed)),
+            ComponentDef::Collider {width, height, offset: (offset_x, offset_y)} => {
+                let collider = Collider {width, height, offset: Vector::new(offset_x, offset_y)};
+                builder.with(BoundingBox(collider.bounds_at(pos))).with(collider)
+            },
+            ComponentDef::CollisionFilter {ref layers, ref mask} => builder.with(CollisionFilter {
+                layers: prefabs.layers_from_names(layers),
+                mask: prefabs.layers_from_names(mask),
+            }),
+            ComponentDef::Sprite {ref texture, region: (x, y, width, height), anchor: (anchor_x, anchor_y)} => {
+                builder.with(Sprite {
+                    texture_id: texture_id(texture),
+                    region: Rect::new(x, y, width, height),
+                    anchor: Point::new(anchor_x, anchor_y),
+                })
+            },
+            ComponentDef::WalkingAnimations {ref texture, frame: (x, y, width, height), anchor: (anchor_x, anchor_y), frames, frame_duration, stride} => {
+                let walk = DirectionalAnimation::standard_walking_animations(
+                    texture_id(texture),
+                    Rect::new(x, y, width, height),
+                    Point::new(anchor_x, anchor_y),
+                    frames,
+                    Duration::from_millis(frame_duration),
+                ).with_stride(stride);
+                with_animation_states(builder, AnimationStates::new(walk, direction), now)
+            },
+            ComponentDef::AnimationStates {ref sheet, anchor: (anchor_x, anchor_y), ref states, ref idle_animation} => {
+                let animation = |tag: &str, playback, stride| DirectionalAnimation::from_spritesheet(
+                    &prefabs.spritesheets[sheet],
+                    tag,
+                    texture_id(sheet),
+                    Point::new(anchor_x, anchor_y),
+                ).expect("bug: spritesheet should have been checked").with_playback(playback).with_stride(stride);
+
+                let walk = &states[&AnimationState::Walk];
+                let mut animation_states = AnimationStates::new(animation(&walk.tag, walk.playback, walk.stride), direction);
+                for (&state, clip) in states {
+                    animation_states.clips.insert(state, animation(&clip.tag, clip.playback, clip.stride));
+                }
+                animation_states.idle_animation = idle_animation.as_ref().map(|clip| DelayedAnimation {
+                    animation: animation(&clip.tag, clip.playback, None),
+                    delay: Duration::from_millis(clip.delay),
+                });
+
+                with_animation_states(builder, animation_states, now)
+            },
+            ComponentDef::Animation {ref sheet, ref tag, anchor: (anchor_x, anchor_y), playback} => {
+                let mut animation = Animation::from_spritesheet(&prefabs.spritesheets[sheet], tag, texture_id(sheet), Point::new(anchor_x, anchor_y))
+                    .expect("bug: spritesheet should have been checked");
+                animation.playback = playback;
+                animation.start(now);
+                // Show the first frame right away instead of waiting for the animator
+                let sprite = animation.frames[0].sprite.clone();
+                builder.with(sprite).with(animation)
+            },
+            ComponentDef::DespawnAfterAnimation => builder.with(DespawnAfterAnimation),
+            ComponentDef::SpawnPoint {ref prefab, delay, count} => builder.with(SpawnPoint {
+                prefab: prefab.clone(),
+                delay: Duration::from_millis(delay),
+                remaining: count,
+                timer: Duration::from_secs(0),
+            }),
+        };
+    }
+
+    builder
+}
+
+/// Adds the given animation states to an entity along with the `Animation` and `Sprite` for its
+/// current state, starting at the game time `now`
+fn with_animation_states<B: Builder>(builder: B, mut animation_states: AnimationStates, now: Duration) -> B {
+    animation_states.state_timer = now;
+    let mut animation = animation_states.current_clip()
+        .expect("bug: entities should start in a state with an animation")
+        .clone();
+    animation.start(now);
+    // Show the first frame right away instead of waiting for the animator
+    let sprite = animation.frames[0].sprite.clone();
+    builder.with(sprite).with(animation).with(animation_states)
+}
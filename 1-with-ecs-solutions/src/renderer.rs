@@ -2,36 +2,72 @@
 //! thread. It cannot be executed in parallel like other systems. Another complication is that it
 //! returns a `Result` whereas normal systems do not return anything.
 
-use specs::{SystemData, ReadStorage, Join, World, prelude::ResourceId};
-use sdl2::{
-    rect::{Point, Rect},
-    render::{WindowCanvas, Texture},
-};
+use specs::{SystemData, ReadStorage, Read, ReadExpect, Join, World, prelude::ResourceId};
+use sdl2::render::{WindowCanvas, Texture};
 
-use crate::components::{BoundingBox, Sprite};
+use crate::vector::Vector;
+use crate::camera::Camera;
+use crate::world_canvas::{WorldCanvas, WorldPoint, WorldRect};
+use crate::resources::TileMap;
+use crate::components::{Position, PreviousPosition, Sprite};
 
 /// Data from the world required by the renderer
 #[derive(SystemData)]
 pub struct RendererData<'a> {
-    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    positions: ReadStorage<'a, Position>,
+    previous_positions: ReadStorage<'a, PreviousPosition>,
     sprites: ReadStorage<'a, Sprite>,
+    camera: ReadExpect<'a, Camera>,
+    tile_map: Read<'a, TileMap>,
 }
 
 impl<'a> RendererData<'a> {
-    pub fn render(&self, canvas: &mut WindowCanvas, textures: &[Texture]) -> Result<(), String> {
-        let RendererData {bounding_boxes, sprites} = self;
-
-        // The screen coordinate system has (0, 0) in its top-left corner whereas the
-        // world coordinate system has (0, 0) in the center of the screen.
-        let (width, height) = canvas.output_size()?;
-        let world_to_screen_offset = Point::new(width as i32 / 2, height as i32 / 2);
-        for (&BoundingBox(bounds), &Sprite {texture_id, region: sprite_rect}) in (bounding_boxes, sprites).join() {
-            let screen_pos = bounds.center() + world_to_screen_offset;
-            let screen_rect = Rect::from_center(screen_pos, sprite_rect.width(), sprite_rect.height());
-
-            canvas.copy(&textures[texture_id], sprite_rect, screen_rect)?;
+    /// Draws the map and then every sprite on top of it onto the canvas, as seen by the camera
+    ///
+    /// The `alpha` parameter is how far (from 0.0 to 1.0) the current frame is between the
+    /// previous simulation step and the current one. Entities that have moved are drawn at that
+    /// point between their previous and current positions so that motion stays smooth even
+    /// when the frame rate doesn't line up with the simulation rate.
+    pub fn render(&self, canvas: &mut WindowCanvas, textures: &[Texture], alpha: f64) -> Result<(), String> {
+        let RendererData {positions, previous_positions, sprites, camera, tile_map} = self;
+
+        // The camera moves between steps too, so it needs to be interpolated just like everything
+        // else
+        let mut canvas = WorldCanvas::new(canvas, camera.interpolated_position(alpha), camera.zoom())?;
+
+        for tile in &tile_map.tiles {
+            let dest = WorldRect {
+                top_left: WorldPoint(Vector::new(tile.bounds.x() as f64, tile.bounds.y() as f64)),
+                width: tile.bounds.width() as f64,
+                height: tile.bounds.height() as f64,
+            };
+            // Large maps have far more tiles than fit on the screen at once
+            if canvas.is_visible(dest) {
+                canvas.copy(&textures[tile.texture_id], tile.region, dest)?;
+            }
+        }
+
+        for (&Position(pos), previous, &Sprite {texture_id, region: sprite_rect, anchor}) in (positions, previous_positions.maybe(), sprites).join() {
+            let world_pos = match previous {
+                Some(&PreviousPosition(prev_pos)) => lerp(prev_pos, pos, alpha),
+                // Entity has never moved, so there is nothing to interpolate
+                None => pos,
+            };
+            // Line up the sprite's anchor with the entity's position
+            let dest = WorldRect {
+                top_left: WorldPoint(world_pos - Vector::new(anchor.x() as f64, anchor.y() as f64)),
+                width: sprite_rect.width() as f64,
+                height: sprite_rect.height() as f64,
+            };
+
+            canvas.copy(&textures[texture_id], sprite_rect, dest)?;
         }
 
         Ok(())
     }
 }
+
+/// Linearly interpolates between two positions
+fn lerp(start: Vector, end: Vector, alpha: f64) -> Vector {
+    start + (end - start) * alpha
+}
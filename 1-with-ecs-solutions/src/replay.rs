@@ -0,0 +1,429 @@
+//! Recording and replaying of game sessions
+//!
+//! Since the simulation is deterministic, a session can be reproduced exactly from its seed, its
+//! level, its world bounds and the input given to each step. A checksum of the world state is stored after
+//! every step so that a replay can detect the first step where it no longer matches the original.
+//!
+//! A replay file is made up of a header followed by one entry per step. All numbers are stored in
+//! little-endian byte order.
+//!
+//! Header:
+//! * the bytes `MGREPLAY` followed by a version number (u8)
+//! * the seed (u64)
+//! * the checksum of the level file (u64), so that the replay isn't played back with another level
+//! * the world bounds: x (i32), y (i32), width (u32), height (u32)
+//!
+//! Each step:
+//! * a tag (u8) saying whether the input of the step is stored:
+//!   * 0: the step has the same input as the previous step, so the checksum comes next
+//!   * 1: the step has new input, which comes next
+//! * only if the tag is 1, the input:
+//!   * time scale flags (u8): bit 0 = paused, bit 1 = step once
+//!   * time scale speed (f64)
+//!   * the number of held actions (u8), then the index in `Action::ALL` of each held action (u8)
+//!     from least to most recently started
+//! * the checksum of the world state after the step (u64)
+
+use std::fs::{self, File};
+use std::path::Path;
+use std::io::{self, Read, Write, BufWriter};
+
+use sdl2::rect::Rect;
+use specs::{World, WorldExt, Join};
+
+use crate::action::Action;
+use crate::resources::{HeldActions, TimeScale, GameStatus};
+use crate::components::Position;
+
+const MAGIC: &[u8] = b"MGREPLAY";
+const VERSION: u8 = 3;
+
+const SAME_INPUT: u8 = 0;
+const NEW_INPUT: u8 = 1;
+
+const PAUSED_FLAG: u8 = 1 << 0;
+const STEP_ONCE_FLAG: u8 = 1 << 1;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Everything that the player controls which can influence a single step of the simulation
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepInput {
+    pub held_actions: HeldActions,
+    pub time_scale: TimeScale,
+}
+
+/// Writes each step of a session to a replay file as it is played
+pub struct Recorder {
+    writer: BufWriter<File>,
+    /// The input of the most recently recorded step
+    prev_input: Option<StepInput>,
+}
+
+impl Recorder {
+    /// Creates a new replay file at the given path for a session with the given seed, level
+    /// checksum (see `file_checksum`) and bounds
+    pub fn create(path: impl AsRef<Path>, seed: u64, level_checksum: u64, world_bounds: Rect) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&seed.to_le_bytes())?;
+        writer.write_all(&level_checksum.to_le_bytes())?;
+        writer.write_all(&world_bounds.x().to_le_bytes())?;
+        writer.write_all(&world_bounds.y().to_le_bytes())?;
+        writer.write_all(&world_bounds.width().to_le_bytes())?;
+        writer.write_all(&world_bounds.height().to_le_bytes())?;
+
+        Ok(Self {writer, prev_input: None})
+    }
+
+    /// Records the input given to a step along with the state of the world after that step
+    pub fn record_step(&mut self, input: &StepInput, world: &World) -> io::Result<()> {
+        if self.prev_input.as_ref() == Some(input) {
+            self.writer.write_all(&[SAME_INPUT])?;
+        } else {
+            self.writer.write_all(&[NEW_INPUT])?;
+            write_input(&mut self.writer, input)?;
+            self.prev_input = Some(input.clone());
+        }
+
+        self.writer.write_all(&world_checksum(world).to_le_bytes())
+    }
+}
+
+/// A recorded step of a session
+#[derive(Debug)]
+struct RecordedStep {
+    input: StepInput,
+    /// The checksum of the world state after the step
+    checksum: u64,
+}
+
+/// A session loaded from a replay file that can be played back step by step
+pub struct Replay {
+    seed: u64,
+    level_checksum: u64,
+    world_bounds: Rect,
+    steps: Vec<RecordedStep>,
+    /// The index of the next step to be played back
+    next_step: usize,
+}
+
+impl Replay {
+    /// Loads a replay from the given file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| format!("unable to read replay '{}': {}", path.display(), err))?;
+
+        Self::parse(&bytes)
+            .map_err(|err| format!("invalid replay '{}': {}", path.display(), err))
+    }
+
+    fn parse(mut bytes: &[u8]) -> io::Result<Self> {
+        let reader = &mut bytes;
+
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not a replay file"));
+        }
+        let version = read_u8(reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported version {}", version)));
+        }
+
+        let seed = read_u64(reader)?;
+        let level_checksum = read_u64(reader)?;
+        let x = read_u32(reader)? as i32;
+        let y = read_u32(reader)? as i32;
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let world_bounds = Rect::new(x, y, width, height);
+
+        let mut steps: Vec<RecordedStep> = Vec::new();
+        while !reader.is_empty() {
+            let input = match read_u8(reader)? {
+                SAME_INPUT => steps.last()
+                    .map(|step| step.input.clone())
+                    .ok_or_else(|| invalid_data("first step must contain input"))?,
+                NEW_INPUT => read_input(reader)?,
+                tag => return Err(invalid_data(format!("invalid step tag {}", tag))),
+            };
+            let checksum = read_u64(reader)?;
+
+            steps.push(RecordedStep {input, checksum});
+        }
+
+        Ok(Self {seed, level_checksum, world_bounds, steps, next_step: 0})
+    }
+
+    /// The seed of the recorded session
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The checksum of the level file of the recorded session
+    pub fn level_checksum(&self) -> u64 {
+        self.level_checksum
+    }
+
+    /// The world bounds of the recorded session
+    pub fn world_bounds(&self) -> Rect {
+        self.world_bounds
+    }
+
+    /// The input for the next step or None if every step has been played back
+    pub fn next_input(&self) -> Option<&StepInput> {
+        self.steps.get(self.next_step).map(|step| &step.input)
+    }
+
+    /// Checks that the state of the world after the next step matches the recording and then
+    /// moves on to the following step
+    ///
+    /// Returns an error describing the first step where the replay diverged from the recording.
+    pub fn verify_step(&mut self, world: &World) -> Result<(), String> {
+        let step_index = self.next_step;
+        let step = self.steps.get(step_index).ok_or("no steps left in replay")?;
+        self.next_step += 1;
+
+        if world_checksum(world) == step.checksum {
+            Ok(())
+        } else {
+            Err(format!("replay diverged from the recording at step {}", step_index))
+        }
+    }
+}
+
+/// Computes a checksum of the contents of a file, e.g. to check that a replay is played back with
+/// the same level that it was recorded with
+pub fn file_checksum(contents: &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, contents)
+}
+
+/// Computes a checksum of all of the world state that matters for comparing two sessions
+fn world_checksum(world: &World) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| hash = fnv1a(hash, bytes);
+
+    let entities = world.entities();
+    let positions = world.read_storage::<Position>();
+    for (entity, Position(pos)) in (&entities, &positions).join() {
+        write(&entity.id().to_le_bytes());
+        // The exact bits are used so that even the smallest difference in position is detected
+        write(&pos.x.to_bits().to_le_bytes());
+        write(&pos.y.to_bits().to_le_bytes());
+    }
+
+    let status = match *world.read_resource::<GameStatus>() {
+        GameStatus::Running => 0,
+        GameStatus::Win => 1,
+        GameStatus::Lose => 2,
+    };
+    write(&[status]);
+
+    hash
+}
+
+/// Adds the given bytes to an FNV-1a hash
+///
+/// This is used instead of the standard library hasher because the checksums are saved in files
+/// and the standard library hasher is not guaranteed to stay the same between releases.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn write_input(writer: &mut impl Write, input: &StepInput) -> io::Result<()> {
+    let StepInput {held_actions, time_scale} = input;
+
+    let mut flags = 0;
+    if time_scale.paused {
+        flags |= PAUSED_FLAG;
+    }
+    if time_scale.step_once {
+        flags |= STEP_ONCE_FLAG;
+    }
+    writer.write_all(&[flags])?;
+    writer.write_all(&time_scale.speed.to_bits().to_le_bytes())?;
+
+    let actions = held_actions.actions();
+    // There are only a few different actions and each can only be held once
+    writer.write_all(&[actions.len() as u8])?;
+    for action in actions {
+        let action_id = Action::ALL.iter().position(|act| act == action)
+            .expect("bug: action missing from Action::ALL");
+        writer.write_all(&[action_id as u8])?;
+    }
+
+    Ok(())
+}
+
+fn read_input(reader: &mut impl Read) -> io::Result<StepInput> {
+    let flags = read_u8(reader)?;
+    let speed = f64::from_bits(read_u64(reader)?);
+    let time_scale = TimeScale {
+        speed,
+        paused: flags & PAUSED_FLAG != 0,
+        step_once: flags & STEP_ONCE_FLAG != 0,
+    };
+
+    let mut held_actions = HeldActions::default();
+    let actions_len = read_u8(reader)?;
+    for _ in 0..actions_len {
+        let action_id = read_u8(reader)?;
+        let action = *Action::ALL.get(action_id as usize)
+            .ok_or_else(|| invalid_data(format!("invalid action {}", action_id)))?;
+        // Actions are stored in the order they were started
+        held_actions.press(action);
+    }
+
+    Ok(StepInput {held_actions, time_scale})
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use specs::Builder;
+
+    use crate::vector::Vector;
+
+    fn world_with_positions(positions: &[(f64, f64)]) -> World {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.insert(GameStatus::Running);
+        for &(x, y) in positions {
+            world.create_entity().with(Position(Vector::new(x, y))).build();
+        }
+        world
+    }
+
+    fn input(actions: &[Action], paused: bool) -> StepInput {
+        let mut held_actions = HeldActions::default();
+        for &action in actions {
+            held_actions.press(action);
+        }
+        let time_scale = TimeScale {paused, ..TimeScale::default()};
+        StepInput {held_actions, time_scale}
+    }
+
+    /// Records the given steps to a file and returns its contents
+    fn record(name: &str, steps: &[(StepInput, &World)]) -> Vec<u8> {
+        let path = env::temp_dir().join(format!("replay-{}-{}", process::id(), name));
+        let mut recorder = Recorder::create(&path, 1234, 5678, Rect::new(-400, -300, 800, 600)).unwrap();
+        for (input, world) in steps {
+            recorder.record_step(input, world).unwrap();
+        }
+        // Dropping the recorder flushes it
+        drop(recorder);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let world = world_with_positions(&[(1.0, 2.0), (-3.5, 4.25)]);
+        let inputs = [
+            input(&[], false),
+            input(&[Action::MoveUp, Action::MoveLeft], false),
+            input(&[Action::MoveUp, Action::MoveLeft], false),
+            input(&[Action::MoveLeft], true),
+        ];
+        let steps: Vec<_> = inputs.iter().map(|input| (input.clone(), &world)).collect();
+        let mut replay = Replay::parse(&record("round_trip", &steps)).unwrap();
+
+        assert_eq!(replay.seed(), 1234);
+        assert_eq!(replay.level_checksum(), 5678);
+        assert_eq!(replay.world_bounds(), Rect::new(-400, -300, 800, 600));
+        for input in &inputs {
+            assert_eq!(replay.next_input(), Some(input));
+            replay.verify_step(&world).unwrap();
+        }
+        assert_eq!(replay.next_input(), None);
+        assert!(replay.verify_step(&world).is_err());
+    }
+
+    #[test]
+    fn repeated_input_is_stored_once() {
+        let world = world_with_positions(&[]);
+        let held = input(&[Action::MoveDown], false);
+        let bytes = record("repeated_input", &[(held.clone(), &world), (held, &world)]);
+
+        let header = MAGIC.len() + 1 + 8 + 8 + 4 * 4;
+        let new_input = 1 + (1 + 8 + 1 + 1) + 8;
+        let same_input = 1 + 8;
+        assert_eq!(bytes.len(), header + new_input + same_input);
+        assert_eq!(bytes[header], NEW_INPUT);
+        assert_eq!(bytes[header + new_input], SAME_INPUT);
+    }
+
+    #[test]
+    fn detects_divergence() {
+        let recorded = world_with_positions(&[(1.0, 2.0)]);
+        // Even the smallest possible difference in position counts
+        let diverged = world_with_positions(&[(1.0, f64::from_bits(2.0f64.to_bits() + 1))]);
+        let held = input(&[], false);
+        let bytes = record("divergence", &[(held.clone(), &recorded), (held.clone(), &recorded), (held, &recorded)]);
+        let mut replay = Replay::parse(&bytes).unwrap();
+
+        assert_eq!(replay.verify_step(&recorded), Ok(()));
+        assert_eq!(replay.verify_step(&diverged), Err("replay diverged from the recording at step 1".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let world = world_with_positions(&[]);
+        let bytes = record("invalid", &[(input(&[], false), &world)]);
+        let header = MAGIC.len() + 1 + 8 + 8 + 4 * 4;
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(Replay::parse(&wrong_magic).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[MAGIC.len()] = VERSION + 1;
+        assert!(Replay::parse(&wrong_version).is_err());
+
+        // The first step has nothing to repeat
+        let mut repeated_first = bytes[..header].to_vec();
+        repeated_first.push(SAME_INPUT);
+        repeated_first.extend_from_slice(&0u64.to_le_bytes());
+        assert!(Replay::parse(&repeated_first).is_err());
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(Replay::parse(truncated).is_err());
+    }
+}
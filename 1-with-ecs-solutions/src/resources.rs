@@ -1,17 +1,133 @@
 use std::time::Duration;
 
+use rand::rngs::StdRng;
+use sdl2::rect::Rect;
+use specs::Entity;
+
+use crate::action::Action;
 use crate::direction::Direction;
+use crate::components::AnimationState;
 
 /// The amount of time elapsed since the last frame
 #[derive(Debug, Default)]
 pub struct TimeDelta(pub Duration);
 
+/// The total amount of game time that has elapsed since the game started
+///
+/// Unlike `Instant::now()`, this only advances when the simulation advances (by the `TimeDelta`
+/// of each step), so timers measured against it stop whenever the game stops.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GameClock {
+    now: Duration,
+}
+
+impl GameClock {
+    /// The current game time
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Moves the clock forward by the given amount of game time
+    pub fn advance(&mut self, time_delta: Duration) {
+        self.now += time_delta;
+    }
+}
+
+/// The source of all randomness in the game
+///
+/// This is always created from an explicit seed so that any run of the game can be reproduced
+/// exactly by using the same seed again.
 #[derive(Debug)]
-pub enum KeyboardEvent {
-    /// Begin to move in the given direction
-    MoveInDirection(Direction),
-    /// Stop moving in the current direction
-    Stop,
+pub struct GameRng(pub StdRng);
+
+/// Controls how quickly game time passes relative to the time step of the simulation
+///
+/// This is applied to the `TimeDelta` and `GameClock` resources, so any system that uses those
+/// (e.g. movement, AI and animation) is automatically paused, slowed down or sped up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeScale {
+    /// The rate at which game time passes (e.g. 0.5 = half speed, 2.0 = double speed)
+    pub speed: f64,
+    /// If true, no game time passes at all
+    pub paused: bool,
+    /// If true while paused, the next step advances the game by exactly one time step
+    pub step_once: bool,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            paused: false,
+            step_once: false,
+        }
+    }
+}
+
+impl TimeScale {
+    /// Returns the amount of game time that should pass during a step of the given length
+    ///
+    /// Any request to step once is used up by calling this method.
+    pub fn scale(&mut self, time_step: Duration) -> Duration {
+        let step_once = self.step_once;
+        self.step_once = false;
+
+        match (self.paused, step_once) {
+            // Advance exactly one step, ignoring the speed
+            (true, true) => time_step,
+            (true, false) => Duration::from_secs(0),
+            (false, _) => time_step.mul_f64(self.speed),
+        }
+    }
+}
+
+/// The actions currently held down by the player, in the order they were started
+///
+/// The most recently pressed direction that is still held is the one that should be used. That
+/// way, if the player presses a second direction before releasing the first, releasing the second
+/// one goes back to the first instead of stopping. A vertical and a horizontal direction held
+/// together combine into a diagonal direction.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HeldActions {
+    actions: Vec<Action>,
+}
+
+impl HeldActions {
+    /// Records that the given action was started, making it the most recent action
+    pub fn press(&mut self, action: Action) {
+        // Move the action to the end of the list since it is now the most recent
+        self.release(action);
+        self.actions.push(action);
+    }
+
+    /// Records that the given action has ended
+    pub fn release(&mut self, action: Action) {
+        self.actions.retain(|&act| act != action);
+    }
+
+    /// All of the held actions, from least to most recently started
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// The direction of movement requested by the held movement actions, if any
+    ///
+    /// Combines the most recently started vertical direction with the most recently started
+    /// horizontal direction, so e.g. holding up and left results in moving diagonally up-left.
+    pub fn direction(&self) -> Option<Direction> {
+        let mut vertical = None;
+        let mut horizontal = None;
+        for direction in self.actions.iter().rev().filter_map(|action| action.direction()) {
+            let vector = direction.into_vector();
+            if vector.x == 0.0 {
+                vertical.get_or_insert(vector);
+            } else {
+                horizontal.get_or_insert(vector);
+            }
+        }
+
+        Direction::from_vector(vertical.unwrap_or_default() + horizontal.unwrap_or_default())
+    }
 }
 
 /// The current status of the game
@@ -21,3 +137,65 @@ pub enum GameStatus {
     Win,
     Lose,
 }
+
+/// Published to the `EventChannel<Collision>` resource by the `CollisionDetector` system whenever
+/// two entities start touching, remain touching or stop touching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collision {
+    /// Whether the entities just started touching, are still touching or just stopped touching
+    pub phase: CollisionPhase,
+    /// The two entities involved in the collision, in no particular order
+    pub entities: (Entity, Entity),
+    /// The area (in world coordinates) where the bounding boxes of the entities overlap
+    ///
+    /// For `CollisionPhase::Exit`, this is the overlap from the last step they were touching.
+    pub overlap: Rect,
+}
+
+/// The stages of a collision between two entities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPhase {
+    /// The entities were not touching on the previous step, but are now
+    Enter,
+    /// The entities were touching on the previous step and still are
+    Stay,
+    /// The entities were touching on the previous step, but no longer are
+    Exit,
+}
+
+/// Published to the `EventChannel<AnimationFinished>` resource by the `Animator` system whenever a
+/// `Once` or `OnceThenHold` animation finishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFinished {
+    /// The entity whose animation finished
+    pub entity: Entity,
+}
+
+/// Published to the `EventChannel<AnimationTrigger>` resource to make an entity with
+/// `AnimationStates` play the animation for an action (e.g. `AnimationState::Attack`)
+///
+/// The event is ignored if the entity has no animation for that state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationTrigger {
+    /// The entity that should play the animation
+    pub entity: Entity,
+    pub state: AnimationState,
+}
+
+/// The tiles of the level's map, drawn beneath every entity
+#[derive(Debug, Default)]
+pub struct TileMap {
+    /// Every tile in the order it should be drawn
+    pub tiles: Vec<Tile>,
+}
+
+/// A single tile of a `TileMap`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tile {
+    /// The texture containing the tileset to copy the tile from
+    pub texture_id: usize,
+    /// The region of the tileset to copy
+    pub region: Rect,
+    /// The area covered by the tile in world coordinates
+    pub bounds: Rect,
+}
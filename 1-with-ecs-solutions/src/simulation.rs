@@ -0,0 +1,198 @@
+//! The simulation owns the game state and the systems that update it. It deliberately knows
+//! nothing about SDL windows, canvases or event pumps so that the exact same game logic can be
+//! run with or without a window (e.g. in tests or on a machine without a display).
+
+use std::time::Duration;
+
+use rand::{SeedableRng, rngs::StdRng};
+use sdl2::rect::Rect;
+use specs::{World, WorldExt, Dispatcher, DispatcherBuilder};
+
+use crate::camera::Camera;
+use crate::spatial_hash::SpatialHash;
+use crate::prefab::Prefabs;
+use crate::resources::{TimeDelta, GameClock, TimeScale, HeldActions, GameStatus, GameRng};
+use crate::systems;
+
+pub struct Simulation {
+    /// The game state
+    world: World,
+    /// The systems that will be run on each step
+    dispatcher: Dispatcher<'static, 'static>,
+    /// The amount of game time simulated by each step
+    time_step: Duration,
+}
+
+impl Simulation {
+    /// Creates a new simulation with no entities. Entities will be kept within `world_bounds` and
+    /// each call to `step` will advance the game by `time_step`. All randomness in the game is
+    /// derived from `seed`, so two simulations with the same seed and input behave identically.
+    pub fn new(world_bounds: Rect, time_step: Duration, seed: u64) -> Self {
+        // Declare the hierarchy of systems that will process entities and components
+        let mut dispatcher = DispatcherBuilder::new()
+            .with(systems::Keyboard, "Keyboard", &[])
+            .with(systems::AI, "AI", &[])
+            .with(systems::Movement::new(world_bounds), "Movement", &["Keyboard", "AI"])
+            .with(systems::Broadphase, "Broadphase", &["Movement"])
+            .with(systems::CollisionDetector::default(), "CollisionDetector", &["Broadphase"])
+            .with(systems::WinLoseChecker::default(), "WinLoseChecker", &["CollisionDetector"])
+            .with(systems::Animator::default(), "Animator", &["Keyboard", "AI", "WinLoseChecker"])
+            .with(systems::Despawner::default(), "Despawner", &["Animator"])
+            // Runs after AI since both use the random number generator, which needs to be used in
+            // the same order on every run
+            .with(systems::Spawning, "Spawning", &["AI"])
+            .with(systems::CameraFollow, "CameraFollow", &["Movement"])
+            .build();
+
+        let mut world = World::new();
+        // Setup the component storages based on the data used by the systems
+        dispatcher.setup(&mut world);
+
+        // Add resources (resources used with ReadExpect/WriteExpect must be added before use)
+        world.insert(TimeDelta(time_step));
+        world.insert(GameClock::default());
+        world.insert(TimeScale::default());
+        world.insert(HeldActions::default());
+        world.insert(GameStatus::Running);
+        world.insert(GameRng(StdRng::seed_from_u64(seed)));
+        world.insert(SpatialHash::default());
+        // Nothing can be spawned while the game is running until some prefabs are added
+        world.insert(Prefabs::default());
+        // Without a window, the camera just looks at the whole level
+        world.insert(Camera::new(world_bounds, (world_bounds.width(), world_bounds.height())));
+
+        Self {world, dispatcher, time_step}
+    }
+
+    /// The game state, e.g. for rendering
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// The game state, e.g. for creating entities
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// The current status of the game
+    pub fn status(&self) -> GameStatus {
+        *self.world.read_resource()
+    }
+
+    /// Advances the game by a single time step using the actions currently held by the player
+    ///
+    /// The amount of game time that actually passes depends on the `TimeScale` resource. No game
+    /// time passes at all while the game is paused.
+    pub fn step(&mut self, held_actions: &HeldActions) {
+        // Inform the systems of the player's input
+        *self.world.write_resource() = held_actions.clone();
+
+        // Store the time elapsed during each step in a resource so that all systems may have
+        // access to it. The time scale lets us pause, slow down or speed up the whole game
+        // without any of the systems needing to know about it.
+        let time_delta = self.world.write_resource::<TimeScale>().scale(self.time_step);
+        *self.world.write_resource() = TimeDelta(time_delta);
+        // Game time only passes when the simulation is stepped
+        self.world.write_resource::<GameClock>().advance(time_delta);
+
+        // Update game state
+        self.dispatcher.dispatch(&self.world);
+        // Apply any lazy updates that occurred during dispatch
+        self.world.maintain();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use specs::Join;
+
+    use crate::vector::Vector;
+    use crate::action::Action;
+    use crate::prefab::{Overrides, ComponentDef};
+    use crate::components::Position;
+
+    const TIME_STEP: Duration = Duration::from_millis(16);
+
+    fn simulation(seed: u64) -> Simulation {
+        Simulation::new(Rect::from_center((0, 0), 800, 600), TIME_STEP, seed)
+    }
+
+    /// Creates an entity from one of the prefabs in `prefabs.ron`
+    fn spawn(simulation: &mut Simulation, name: &str, position: Vector, components: Vec<ComponentDef>) {
+        let prefabs = Prefabs::load("prefabs.ron").unwrap();
+        let world = simulation.world_mut();
+        let GameRng(mut rng) = world.remove().unwrap();
+        let overrides = Overrides {position, components, ..Overrides::default()};
+        prefabs.spawn(world, name, &overrides, &mut rng).unwrap();
+        world.insert(GameRng(rng));
+    }
+
+    /// Steps the simulation with the actions returned for each step until the game is over or the
+    /// given number of steps have passed
+    fn run(simulation: &mut Simulation, steps: usize, mut input: impl FnMut(usize) -> HeldActions) -> GameStatus {
+        for step in 0..steps {
+            simulation.step(&input(step));
+            if simulation.status() != GameStatus::Running {
+                break;
+            }
+        }
+        simulation.status()
+    }
+
+    fn holding(actions: &[Action]) -> HeldActions {
+        let mut held_actions = HeldActions::default();
+        for &action in actions {
+            held_actions.press(action);
+        }
+        held_actions
+    }
+
+    #[test]
+    fn reaching_the_goal_wins() {
+        let mut simulation = simulation(1);
+        spawn(&mut simulation, "pink_tree", Vector::new(0.0, -100.0), Vec::new());
+        spawn(&mut simulation, "bardo", Vector::new(0.0, 200.0), Vec::new());
+
+        assert_eq!(run(&mut simulation, 10, |_| HeldActions::default()), GameStatus::Running);
+        assert_eq!(run(&mut simulation, 300, |_| holding(&[Action::MoveUp])), GameStatus::Win);
+    }
+
+    #[test]
+    fn touching_an_enemy_loses() {
+        let mut simulation = simulation(1);
+        spawn(&mut simulation, "bardo", Vector::new(0.0, 200.0), Vec::new());
+        // An enemy that stands still
+        spawn(&mut simulation, "reaper", Vector::new(0.0, 0.0), vec![
+            ComponentDef::Enemy {movement_speed: 0.0, direction_change_delay: 200},
+            ComponentDef::Velocity {speed: 0.0},
+        ]);
+
+        assert_eq!(run(&mut simulation, 600, |_| holding(&[Action::MoveUp])), GameStatus::Lose);
+    }
+
+    #[test]
+    fn same_seed_and_input_give_same_world() {
+        let positions = |seed| {
+            let mut simulation = simulation(seed);
+            spawn(&mut simulation, "bardo", Vector::new(0.0, 250.0), Vec::new());
+            spawn(&mut simulation, "tree", Vector::new(-200.0, 0.0), Vec::new());
+            for i in 0..6 {
+                spawn(&mut simulation, "reaper", Vector::new(i as f64 * 120.0 - 300.0, -150.0), Vec::new());
+            }
+
+            // Walk left and right, changing direction every second
+            run(&mut simulation, 600, |step| match step / 60 % 2 {
+                0 => holding(&[Action::MoveLeft]),
+                _ => holding(&[Action::MoveRight, Action::MoveUp]),
+            });
+
+            let positions = simulation.world().read_storage::<Position>();
+            positions.join().map(|&Position(pos)| (pos.x, pos.y)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(positions(42), positions(42));
+        assert_ne!(positions(42), positions(43));
+    }
+}
@@ -0,0 +1,153 @@
+//! A spatial hash divides the world into a grid of equally sized cells and remembers which
+//! entities overlap each cell. Finding the entities near some area then only requires looking at
+//! the few cells that area covers instead of checking every entity in the world. This is known as
+//! a "broadphase" since it quickly narrows down which pairs of entities could possibly collide.
+
+use std::collections::HashMap;
+
+use sdl2::rect::Rect;
+use specs::Entity;
+
+/// The default size (in pixels) of each cell, about the size of a typical entity
+const DEFAULT_CELL_SIZE: u32 = 64;
+
+/// Finds entities by the area of the world that they occupy
+///
+/// Rebuilt on every step by the `Broadphase` system from the `BoundingBox` of each entity.
+#[derive(Debug)]
+pub struct SpatialHash {
+    /// The width and height (in pixels) of each cell in the grid
+    cell_size: i32,
+    /// The entities (and their bounding boxes) overlapping each cell, keyed by cell coordinates
+    cells: HashMap<(i32, i32), Vec<(Entity, Rect)>>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl SpatialHash {
+    /// Creates an empty spatial hash with cells of the given size
+    ///
+    /// The cell size should be close to the size of a typical entity. Cells that are much smaller
+    /// mean each entity is stored in many cells, and cells that are much larger mean each query
+    /// has to check many entities that are nowhere near the queried area.
+    pub fn new(cell_size: u32) -> Self {
+        Self {
+            cell_size: cell_size as i32,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Removes every entity from the spatial hash
+    pub fn clear(&mut self) {
+        // Keep the cells around so their memory can be reused when the hash is rebuilt
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    /// Adds an entity occupying the given area of the world
+    pub fn insert(&mut self, entity: Entity, bounds: Rect) {
+        for cell in self.cells_overlapping(bounds) {
+            self.cells.entry(cell).or_default().push((entity, bounds));
+        }
+    }
+
+    /// Returns every entity (along with its bounding box) whose bounding box intersects the given
+    /// area of the world
+    ///
+    /// The entities are returned in a consistent order (sorted by entity) so that the results do
+    /// not depend on the order in which entities were inserted.
+    pub fn query(&self, area: Rect) -> Vec<(Entity, Rect)> {
+        let mut found: Vec<(Entity, Rect)> = self.cells_overlapping(area)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(|(_, bounds)| bounds.has_intersection(area))
+            .copied()
+            .collect();
+
+        // An entity that spans several cells will be found once for each of those cells
+        found.sort_by_key(|&(entity, _)| entity);
+        found.dedup_by_key(|&mut (entity, _)| entity);
+        found
+    }
+
+    /// Returns the coordinates of every cell that the given area overlaps
+    fn cells_overlapping(&self, area: Rect) -> impl Iterator<Item=(i32, i32)> {
+        let cell_size = self.cell_size;
+        // The right and bottom edges of a Rect are exclusive
+        let (left, right) = (area.left().div_euclid(cell_size), (area.right() - 1).div_euclid(cell_size));
+        let (top, bottom) = (area.top().div_euclid(cell_size), (area.bottom() - 1).div_euclid(cell_size));
+
+        (top..=bottom).flat_map(move |y| (left..=right).map(move |x| (x, y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use specs::{World, WorldExt, Builder};
+
+    fn entities(count: usize) -> Vec<Entity> {
+        let mut world = World::new();
+        (0..count).map(|_| world.create_entity().build()).collect()
+    }
+
+    #[test]
+    fn finds_rects_spanning_several_cells_once() {
+        let entities = entities(2);
+        let mut hash = SpatialHash::new(10);
+        // Covers cells (0, 0) to (2, 1)
+        let wide = Rect::new(5, 5, 20, 10);
+        hash.insert(entities[0], wide);
+        hash.insert(entities[1], Rect::new(40, 40, 5, 5));
+
+        assert_eq!(hash.query(Rect::new(0, 0, 30, 20)), vec![(entities[0], wide)]);
+        assert_eq!(hash.query(Rect::new(20, 12, 1, 1)), vec![(entities[0], wide)]);
+        assert_eq!(hash.query(Rect::new(0, 0, 50, 50)), vec![(entities[0], wide), (entities[1], Rect::new(40, 40, 5, 5))]);
+    }
+
+    #[test]
+    fn only_returns_rects_that_intersect_the_area() {
+        let entities = entities(1);
+        let mut hash = SpatialHash::new(10);
+        hash.insert(entities[0], Rect::new(0, 0, 4, 4));
+
+        // Same cell, but not touching
+        assert_eq!(hash.query(Rect::new(6, 6, 2, 2)), vec![]);
+        // The right and bottom edges of a Rect are exclusive, so these only touch at the edge
+        assert_eq!(hash.query(Rect::new(4, 0, 2, 2)), vec![]);
+        assert_eq!(hash.query(Rect::new(0, 4, 2, 2)), vec![]);
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let entities = entities(2);
+        let mut hash = SpatialHash::new(10);
+        // Entirely within cell (-1, -1)
+        let negative = Rect::new(-10, -10, 10, 10);
+        // Straddles the origin
+        let centered = Rect::new(-3, -3, 6, 6);
+        hash.insert(entities[0], negative);
+        hash.insert(entities[1], centered);
+
+        assert_eq!(hash.query(Rect::new(-9, -9, 1, 1)), vec![(entities[0], negative)]);
+        assert_eq!(hash.query(Rect::new(2, 2, 1, 1)), vec![(entities[1], centered)]);
+        assert_eq!(hash.query(Rect::new(0, 0, 5, 5)), vec![(entities[1], centered)]);
+        assert_eq!(hash.query(Rect::new(-20, -20, 10, 10)), vec![]);
+    }
+
+    #[test]
+    fn clear_removes_everything() {
+        let entities = entities(1);
+        let mut hash = SpatialHash::new(10);
+        hash.insert(entities[0], Rect::new(0, 0, 30, 30));
+        hash.clear();
+
+        assert_eq!(hash.query(Rect::new(0, 0, 30, 30)), vec![]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Spritesheets are exported from Aseprite (File > Export Sprite Sheet, with "JSON Data" checked)
+//! along with a JSON file that describes where each frame is in the image, how long it lasts and
+//! which frames make up each animation (called "tags" in Aseprite). The frames can be laid out in
+//! any way and do not need to be the same size.
+//!
+//! Both the "Hash" and "Array" JSON formats are supported. Frames must not be rotated, but they may
+//! be trimmed.
+
+use std::fs;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::collections::HashMap;
+
+use sdl2::rect::{Point, Rect};
+use serde::{Deserialize, Deserializer, de::{Visitor, SeqAccess, MapAccess}};
+
+/// A spritesheet loaded from a JSON file exported by Aseprite
+#[derive(Debug)]
+pub struct SpriteSheet {
+    /// The path to the image containing the frames
+    image: PathBuf,
+    frames: Vec<Frame>,
+    /// The indexes of the frames in each animation, by tag name
+    tags: HashMap<String, Vec<usize>>,
+}
+
+/// A single frame of an animation
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    /// The region of the image containing the frame
+    pub region: Rect,
+    /// The position of the region within the frame before any transparent pixels were trimmed off
+    /// of its edges
+    pub trim_offset: Point,
+    /// The amount of time the frame is shown for
+    pub duration: Duration,
+}
+
+impl SpriteSheet {
+    /// Loads a spritesheet from the given JSON file exported by Aseprite
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("unable to read spritesheet '{}': {}", path.display(), err))?;
+        let sheet_file: SheetFile = serde_json::from_str(&json)
+            .map_err(|err| format!("invalid spritesheet '{}': {}", path.display(), err))?;
+
+        // The image is referenced relative to the JSON file
+        let sheet_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::from_file(sheet_file, sheet_dir)
+            .map_err(|err| format!("invalid spritesheet '{}': {}", path.display(), err))
+    }
+
+    fn from_file(sheet_file: SheetFile, sheet_dir: &Path) -> Result<Self, String> {
+        let SheetFile {frames: FramesFile(frames), meta} = sheet_file;
+
+        let frames = frames.into_iter().enumerate().map(|(index, frame)| {
+            let FrameFile {frame: region, rotated, sprite_source_size, duration} = frame;
+            if rotated {
+                return Err(format!("frame {} is rotated, which is not supported", index));
+            }
+            if region.w == 0 || region.h == 0 {
+                return Err(format!("frame {} is empty", index));
+            }
+
+            Ok(Frame {
+                region: Rect::new(region.x, region.y, region.w, region.h),
+                trim_offset: Point::new(sprite_source_size.x, sprite_source_size.y),
+                duration: Duration::from_millis(duration),
+            })
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let mut tags = HashMap::new();
+        for TagFile {name, from, to, direction} in meta.frame_tags {
+            if from > to || to >= frames.len() {
+                return Err(format!("tag '{}' refers to frames that do not exist", name));
+            }
+
+            let forward: Vec<_> = (from..=to).collect();
+            let backward: Vec<_> = forward.iter().rev().copied().collect();
+            // Ping-pong animations go back and forth without repeating the frames at either end
+            let ping_pong = |there: &[usize], back: &[usize]| {
+                let mut frames = there.to_vec();
+                frames.extend(back.iter().skip(1).take(back.len().saturating_sub(2)));
+                frames
+            };
+            let tag_frames = match direction.as_str() {
+                "forward" => forward,
+                "reverse" => backward,
+                "pingpong" => ping_pong(&forward, &backward),
+                "pingpong_reverse" => ping_pong(&backward, &forward),
+                _ => return Err(format!("tag '{}' has unknown direction '{}'", name, direction)),
+            };
+
+            tags.insert(name, tag_frames);
+        }
+
+        Ok(Self {
+            image: sheet_dir.join(meta.image),
+            frames,
+            tags,
+        })
+    }
+
+    /// The path to the image containing the frames
+    pub fn image(&self) -> &Path {
+        &self.image
+    }
+
+    /// Returns the frames of the animation with the given tag name, if there is one
+    pub fn animation(&self, tag: &str) -> Option<Vec<Frame>> {
+        let frames = self.tags.get(tag)?;
+        Some(frames.iter().map(|&index| self.frames[index]).collect())
+    }
+}
+
+// The structures below mirror the parts of Aseprite's JSON format that are used by the game
+
+#[derive(Debug, Deserialize)]
+struct SheetFile {
+    frames: FramesFile,
+    meta: MetaFile,
+}
+
+/// The frames of the spritesheet, in order
+///
+/// In the "Hash" format, the frames are stored in an object keyed by their file name instead of
+/// in an array. The order of the frames still matters, so this can't be deserialized as a map.
+#[derive(Debug)]
+struct FramesFile(Vec<FrameFile>);
+
+impl<'de> Deserialize<'de> for FramesFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = FramesFile;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array or object of frames")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut frames = Vec::new();
+                while let Some(frame) = seq.next_element()? {
+                    frames.push(frame);
+                }
+                Ok(FramesFile(frames))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut frames = Vec::new();
+                while let Some((_, frame)) = map.next_entry::<String, _>()? {
+                    frames.push(frame);
+                }
+                Ok(FramesFile(frames))
+            }
+        }
+
+        deserializer.deserialize_any(FramesVisitor)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameFile {
+    frame: RectFile,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: RectFile,
+    /// In milliseconds
+    duration: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RectFile {
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetaFile {
+    image: String,
+    #[serde(default)]
+    frame_tags: Vec<TagFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagFile {
+    name: String,
+    from: usize,
+    to: usize,
+    /// One of "forward", "reverse", "pingpong" or "pingpong_reverse"
+    direction: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame in Aseprite's JSON format at the given position in the image
+    fn frame_json(x: i32, duration: u64) -> String {
+        format!(r#"{{
+            "frame": {{"x": {}, "y": 0, "w": 16, "h": 24}},
+            "rotated": false,
+            "spriteSourceSize": {{"x": 2, "y": 4, "w": 16, "h": 24}},
+            "duration": {}
+        }}"#, x, duration)
+    }
+
+    /// Parses a spritesheet with the given frames (as a JSON array or object) and tags
+    fn sheet(frames: &str, tags: &str) -> Result<SpriteSheet, String> {
+        let json = format!(r#"{{
+            "frames": {},
+            "meta": {{"image": "sheet.png", "frameTags": [{}]}}
+        }}"#, frames, tags);
+        SpriteSheet::from_file(serde_json::from_str(&json).unwrap(), Path::new("assets"))
+    }
+
+    fn tag_json(name: &str, from: usize, to: usize, direction: &str) -> String {
+        format!(r#"{{"name": "{}", "from": {}, "to": {}, "direction": "{}"}}"#, name, from, to, direction)
+    }
+
+    /// The x coordinate of each frame of an animation, which is enough to tell the frames apart
+    fn frame_xs(sheet: &SpriteSheet, tag: &str) -> Vec<i32> {
+        sheet.animation(tag).unwrap().iter().map(|frame| frame.region.x()).collect()
+    }
+
+    #[test]
+    fn array_frames() {
+        let frames = format!("[{}, {}]", frame_json(0, 100), frame_json(16, 250));
+        let sheet = sheet(&frames, &tag_json("walk", 0, 1, "forward")).unwrap();
+
+        assert_eq!(sheet.image(), Path::new("assets/sheet.png"));
+        let walk = sheet.animation("walk").unwrap();
+        assert_eq!(walk.len(), 2);
+        assert_eq!(walk[0].region, Rect::new(0, 0, 16, 24));
+        assert_eq!(walk[0].trim_offset, Point::new(2, 4));
+        assert_eq!(walk[0].duration, Duration::from_millis(100));
+        assert_eq!(walk[1].region, Rect::new(16, 0, 16, 24));
+        assert_eq!(walk[1].duration, Duration::from_millis(250));
+        assert!(sheet.animation("run").is_none());
+    }
+
+    #[test]
+    fn hash_frames_keep_their_order() {
+        // The keys are deliberately not in alphabetical order
+        let frames = format!(r#"{{"b.png": {}, "a.png": {}, "c.png": {}}}"#,
+            frame_json(0, 100), frame_json(16, 100), frame_json(32, 100));
+        let sheet = sheet(&frames, &tag_json("walk", 0, 2, "forward")).unwrap();
+
+        assert_eq!(frame_xs(&sheet, "walk"), vec![0, 16, 32]);
+    }
+
+    #[test]
+    fn tag_directions() {
+        let frames = format!("[{}, {}, {}, {}]",
+            frame_json(0, 100), frame_json(16, 100), frame_json(32, 100), frame_json(48, 100));
+        let tags = [
+            tag_json("forward", 1, 3, "forward"),
+            tag_json("reverse", 1, 3, "reverse"),
+            tag_json("pingpong", 0, 3, "pingpong"),
+            tag_json("pingpong_reverse", 0, 3, "pingpong_reverse"),
+            tag_json("single", 2, 2, "pingpong"),
+        ];
+        let sheet = sheet(&frames, &tags.join(",")).unwrap();
+
+        assert_eq!(frame_xs(&sheet, "forward"), vec![16, 32, 48]);
+        assert_eq!(frame_xs(&sheet, "reverse"), vec![48, 32, 16]);
+        // The frames at either end are not repeated when the animation loops
+        assert_eq!(frame_xs(&sheet, "pingpong"), vec![0, 16, 32, 48, 32, 16]);
+        assert_eq!(frame_xs(&sheet, "pingpong_reverse"), vec![48, 32, 16, 0, 16, 32]);
+        assert_eq!(frame_xs(&sheet, "single"), vec![32]);
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        let frames = format!("[{}, {}]", frame_json(0, 100), frame_json(16, 100));
+
+        assert!(sheet(&frames, &tag_json("walk", 0, 2, "forward")).is_err());
+        assert!(sheet(&frames, &tag_json("walk", 1, 0, "forward")).is_err());
+        assert!(sheet(&frames, &tag_json("walk", 0, 1, "sideways")).is_err());
+    }
+}
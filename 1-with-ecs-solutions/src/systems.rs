@@ -1,11 +1,21 @@
 mod keyboard;
 mod movement;
+mod broadphase;
+mod collision_detector;
+mod camera_follow;
 mod animator;
 mod ai;
 mod win_lose_checker;
+mod spawning;
+mod despawner;
 
 pub use keyboard::*;
 pub use movement::*;
+pub use broadphase::*;
+pub use collision_detector::*;
+pub use camera_follow::*;
 pub use animator::*;
 pub use ai::*;
 pub use win_lose_checker::*;
+pub use spawning::*;
+pub use despawner::*;
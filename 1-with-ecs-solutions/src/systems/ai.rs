@@ -1,9 +1,8 @@
-use std::time::Instant;
-
-use rand::{Rng, thread_rng};
-use specs::{System, SystemData, WriteStorage, Join, World, prelude::ResourceId};
+use rand::Rng;
+use specs::{System, SystemData, ReadExpect, WriteExpect, WriteStorage, Join, World, prelude::ResourceId};
 
 use crate::direction::Direction;
+use crate::resources::{GameClock, GameRng};
 use crate::components::{Enemy, Velocity};
 
 pub struct AI;
@@ -13,32 +12,42 @@ pub struct AI;
 pub struct AIData<'a> {
     enemies: WriteStorage<'a, Enemy>,
     velocities: WriteStorage<'a, Velocity>,
+    clock: ReadExpect<'a, GameClock>,
+    rng: WriteExpect<'a, GameRng>,
 }
 
 impl<'a> System<'a> for AI {
     type SystemData = AIData<'a>;
 
     fn run(&mut self, data: Self::SystemData) {
-        let AIData {mut enemies, mut velocities} = data;
+        let AIData {mut enemies, mut velocities, clock, mut rng} = data;
+        let GameRng(rng) = &mut *rng;
+        let now = clock.now();
 
-        let mut rng = thread_rng();
-        for (enemy, velocity) in (&mut enemies, &mut velocities).join() {
+        for (enemy, Velocity(velocity)) in (&mut enemies, &mut velocities).join() {
             // Avoid changing the direction too rapidly by only doing it every so often
-            if enemy.direction_timer.elapsed() >= enemy.direction_change_delay {
+            if now - enemy.direction_timer >= enemy.direction_change_delay {
                 // Generate a new random direction
-                velocity.direction = match rng.gen_range(1, 101) {
+                let new_direction = match rng.gen_range(1, 101) {
                     // 60% probability of staying in the same direction
-                    1..=60 => velocity.direction,
-                    // 10% chance of changing to some random direction (one of which could be the same)
-                    61..=70 => Direction::Up,
-                    71..=80 => Direction::Down,
-                    81..=90 => Direction::Left,
-                    91..=100 => Direction::Right,
+                    1..=60 => None,
+                    // 5% chance of changing to some random direction (one of which could be the same)
+                    61..=65 => Some(Direction::Up),
+                    66..=70 => Some(Direction::Down),
+                    71..=75 => Some(Direction::Left),
+                    76..=80 => Some(Direction::Right),
+                    81..=85 => Some(Direction::UpLeft),
+                    86..=90 => Some(Direction::UpRight),
+                    91..=95 => Some(Direction::DownLeft),
+                    96..=100 => Some(Direction::DownRight),
                     _ => unreachable!(),
                 };
+                if let Some(direction) = new_direction {
+                    *velocity = direction.into_vector() * enemy.movement_speed;
+                }
 
                 // Reset the direction timer
-                enemy.direction_timer = Instant::now();
+                enemy.direction_timer = now;
             }
         }
     }
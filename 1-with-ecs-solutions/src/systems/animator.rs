@@ -1,76 +1,344 @@
-use std::time::Instant;
+use std::collections::HashMap;
 
-use specs::{System, SystemData, Entities, ReadStorage, WriteStorage, Join, World, prelude::ResourceId};
+use specs::{System, SystemData, Entities, ReadExpect, ReadStorage, WriteStorage, Read, Write, Join, World, ReaderId, prelude::ResourceId};
+use specs::shrev::EventChannel;
 
-use crate::components::{Velocity, Animation, Sprite, MovementAnimations};
+use crate::direction::Direction;
+use crate::resources::{GameClock, AnimationFinished, AnimationTrigger};
+use crate::components::{Position, PreviousPosition, Velocity, Animation, Playback, Sprite, AnimationStates, AnimationState, Dead};
 
-pub struct Animator;
+#[derive(Default)]
+pub struct Animator {
+    /// Used to read the animation triggers published since the last time this system ran
+    trigger_reader: Option<ReaderId<AnimationTrigger>>,
+}
 
 /// Data from the world required by the system
 #[derive(SystemData)]
 pub struct AnimatorData<'a> {
     entities: Entities<'a>,
     velocities: ReadStorage<'a, Velocity>,
-    movement_animations: ReadStorage<'a, MovementAnimations>,
+    positions: ReadStorage<'a, Position>,
+    previous_positions: ReadStorage<'a, PreviousPosition>,
+    dead: ReadStorage<'a, Dead>,
+    animation_states: WriteStorage<'a, AnimationStates>,
     animations: WriteStorage<'a, Animation>,
     sprites: WriteStorage<'a, Sprite>,
+    clock: ReadExpect<'a, GameClock>,
+    animation_triggers: Read<'a, EventChannel<AnimationTrigger>>,
+    animation_finished: Write<'a, EventChannel<AnimationFinished>>,
 }
 
 impl<'a> System<'a> for Animator {
     type SystemData = AnimatorData<'a>;
 
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.trigger_reader = Some(world.fetch_mut::<EventChannel<AnimationTrigger>>().register_reader());
+    }
+
     fn run(&mut self, data: Self::SystemData) {
         let AnimatorData {
             entities,
             velocities,
-            movement_animations,
+            positions,
+            previous_positions,
+            dead,
+            mut animation_states,
             mut animations,
             mut sprites,
+            clock,
+            animation_triggers,
+            mut animation_finished,
         } = data;
+        let trigger_reader = self.trigger_reader.as_mut()
+            .expect("bug: Animator was not setup");
+        let now = clock.now();
 
-        // Update the Animation component of every entity with Velocity and MovementAnimations
+        // Only the most recent trigger for each entity matters
+        let triggers: HashMap<_, _> = animation_triggers.read(trigger_reader)
+            .map(|&AnimationTrigger {entity, state}| (entity, state))
+            .collect();
+
+        // Update the state of every entity with AnimationStates and choose its Animation to match
         // This loop can be made into a separate System for increased parallelism as the game grows
-        for (entity, &Velocity {speed, direction}, move_animations) in (&*entities, &velocities, &movement_animations).join() {
-            // Clone the frames (cheaply thanks to Arc) so we can use them without keeping a
-            // reference to the animation around. This helps us mutate `animations` without keeping
-            // an immutable reference to it around.
-            let anim_frames = animations.get(entity).map(|anim| anim.frames.clone());
-            // Stop animating movement if the entity has stopped
-            if speed == 0 && anim_frames.is_some() {
-                animations.remove(entity);
-                continue;
+        for (entity, states, velocity, dead) in (&*entities, &mut animation_states, velocities.maybe(), dead.maybe()).join() {
+            let movement = velocity.and_then(|&Velocity(velocity)| Direction::from_vector(velocity));
+            // Keep facing the same way after stopping
+            if let Some(direction) = movement {
+                states.facing = direction;
+            }
+
+            let triggered = triggers.get(&entity).copied()
+                .filter(|state| states.clips.contains_key(state));
+            // Looping animations would never finish, so they only play through once as an action
+            let action_playing = animations.get(entity)
+                .is_some_and(|anim| anim.is_one_shot() && !anim.finished);
+            let next_state = if dead.is_some() {
+                AnimationState::Dead
+            } else if let Some(state) = triggered {
+                state
+            } else if matches!(states.state, AnimationState::Attack | AnimationState::Hurt) && action_playing {
+                // Actions play all the way through before going back to standing or walking
+                states.state
+            } else if movement.is_some() {
+                AnimationState::Walk
+            } else {
+                AnimationState::Idle
+            };
+
+            // Triggering an action while it is already playing starts it over
+            let restart = next_state != states.state || triggered.is_some();
+            let previous_state = (states.state, states.state_timer);
+            if restart {
+                states.state = next_state;
+                states.state_timer = now;
             }
 
-            let dir_anim = move_animations.animation_for(direction);
+            // Standing still for long enough plays the idle animation instead of the idle frames
+            let idle_animation = states.idle_animation.as_ref()
+                .filter(|idle| states.state == AnimationState::Idle && now - states.state_timer >= idle.delay)
+                .map(|idle| idle.animation.animation_for(states.facing));
+            let clip = match idle_animation.or_else(|| states.current_clip()) {
+                Some(clip) => clip,
+                // Without an animation for the state (only possible for `Dead`), the entity stays
+                // on whatever frame it was showing
+                None => {
+                    if restart {
+                        animations.remove(entity);
+                    }
+                    continue;
+                },
+            };
 
             // Testing for equality of two Vecs would normally be quite expensive, but luckily
             // since we are using Arc<Vec<_>>, this will check if the pointers are equal first
             // (thus making the comparision very cheap in most cases)
-            let needs_update = match anim_frames {
+            let needs_update = restart || match animations.get(entity) {
                 // Only update if a different animation is currently playing
-                Some(anim_frames) => anim_frames != dir_anim.frames,
+                Some(anim) => anim.frames != clip.frames,
                 // No animation currently, so we can update it unconditionally
                 None => true,
             };
 
             if needs_update {
-                animations.insert(entity, dir_anim.clone())
+                // The new animation starts right now, so its first frame is shown immediately
+                let mut clip = clip.clone();
+                clip.start(now);
+                // Skip a first frame that looks the same as what is already shown (e.g. idle frames
+                // taken from the walking animation) so that starting to move always shows a step,
+                // even if a key is only tapped for a moment
+                let unchanged = sprites.get(entity).is_some_and(|sprite| *sprite == clip.frames[0].sprite);
+                if unchanged && clip.frames.len() > 1 {
+                    clip.advance();
+                }
+                if let Some(sprite) = sprites.get_mut(entity) {
+                    *sprite = clip.frames[clip.current_frame].sprite.clone();
+                }
+                // Actions go back to whatever they interrupted once they finish. Interrupting
+                // another action goes back to what that action interrupted instead.
+                if clip.playback == Playback::Once {
+                    match animations.remove(entity) {
+                        Some(current) if current.is_one_shot() => clip.previous = current.previous,
+                        Some(current) => {
+                            clip.previous = Some(Box::new(current));
+                            states.interrupted = Some(previous_state);
+                        },
+                        None => states.interrupted = None,
+                    }
+                } else {
+                    states.interrupted = None;
+                }
+                animations.insert(entity, clip)
                     .expect("failed to update animation");
             }
         }
 
         // Advance each animation and update the current sprite to be rendered when necessary
-        for (anim, sprite) in (&mut animations, &mut sprites).join() {
-            // Advance the animation frame if enough time has elapsed
-            if anim.frame_timer.elapsed() >= anim.frames[anim.current_frame].duration {
-                // Loop back to the first frame if we've advanced past the end
-                anim.current_frame = (anim.current_frame + 1) % anim.frames.len();
-                // Reset the frame timer
-                anim.frame_timer = Instant::now();
-
-                // Current frame has changed, so we need to update the sprite
-                *sprite = anim.frames[anim.current_frame].sprite.clone();
+        let mut finished = Vec::new();
+        for (entity, anim, sprite, position, previous_position) in (&*entities, &mut animations, &mut sprites, positions.maybe(), previous_positions.maybe()).join() {
+            if anim.finished {
+                continue;
             }
+
+            let next_frame = match anim.stride {
+                // Advance the animation frame once the entity has travelled far enough
+                Some(stride) => {
+                    // This is the distance actually travelled, so walking into a wall doesn't
+                    // move the entity's feet
+                    let distance = match (position, previous_position) {
+                        (Some(&Position(position)), Some(&PreviousPosition(previous))) => (position - previous).length(),
+                        _ => 0.0,
+                    };
+                    anim.distance += distance;
+
+                    let next_frame = distance > 0.0 && anim.distance >= stride;
+                    if next_frame {
+                        // Any excess distance counts towards the next frame
+                        anim.distance -= stride;
+                    }
+                    next_frame
+                },
+                // Advance the animation frame if enough time has elapsed
+                None => {
+                    let frame_duration = anim.frames[anim.current_frame].duration;
+                    let next_frame = now - anim.frame_timer >= frame_duration;
+                    if next_frame {
+                        // The next frame begins when the current one was supposed to end, not
+                        // right now. Otherwise any excess time would be added on to the next
+                        // frame and the animation would slowly drift behind.
+                        anim.frame_timer += frame_duration;
+                    }
+                    next_frame
+                },
+            };
+
+            if next_frame {
+                if anim.advance() {
+                    // Current frame has changed, so we need to update the sprite
+                    *sprite = anim.frames[anim.current_frame].sprite.clone();
+                } else {
+                    // The last frame of a one-shot animation has been shown in full
+                    anim.finished = true;
+                    finished.push(entity);
+                }
+            }
+        }
+
+        for entity in finished {
+            animation_finished.single_write(AnimationFinished {entity});
+
+            let anim = animations.get_mut(entity)
+                .expect("bug: finished animation should still exist");
+            // Held animations stay on their last frame until something else replaces them
+            if anim.playback != Playback::Once {
+                continue;
+            }
+
+            match anim.previous.take() {
+                Some(previous) => {
+                    // The interrupted state carries on as if it had never stopped, so that the
+                    // animation isn't started over on the next step
+                    if let Some(states) = animation_states.get_mut(entity) {
+                        if let Some((state, state_timer)) = states.interrupted.take() {
+                            states.state = state;
+                            states.state_timer = state_timer;
+                        }
+                    }
+
+                    // The interrupted animation picks up from the frame it was on
+                    let mut previous = *previous;
+                    previous.frame_timer = now;
+                    if let Some(sprite) = sprites.get_mut(entity) {
+                        *sprite = previous.frames[previous.current_frame].sprite.clone();
+                    }
+                    *anim = previous;
+                },
+                None => {
+                    animations.remove(entity);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use sdl2::rect::{Point, Rect};
+    use specs::{WorldExt, Builder, RunNow};
+
+    use crate::vector::Vector;
+    use crate::components::{Frame, DirectionalAnimation};
+
+    /// A sprite that can be told apart from the others by its x coordinate
+    fn sprite(x: i32) -> Sprite {
+        Sprite {texture_id: 0, region: Rect::new(x, 0, 16, 16), anchor: Point::new(8, 16)}
+    }
+
+    /// An animation with a frame every 100ms for each of the given sprites, used in every direction
+    fn clip(xs: &[i32], playback: Playback) -> DirectionalAnimation {
+        let frames = xs.iter().map(|&x| Frame {sprite: sprite(x), duration: Duration::from_millis(100)}).collect();
+        let animation = Animation::new(frames, playback);
+        DirectionalAnimation {
+            up: animation.clone(),
+            down: animation.clone(),
+            left: animation.clone(),
+            right: animation,
+            up_left: None,
+            up_right: None,
+            down_left: None,
+            down_right: None,
+        }
+    }
+
+    fn run_at(animator: &mut Animator, world: &mut World, millis: u64) {
+        let mut clock = world.write_resource::<GameClock>();
+        let elapsed = Duration::from_millis(millis) - clock.now();
+        clock.advance(elapsed);
+        drop(clock);
+        animator.run_now(world);
+    }
+
+    #[test]
+    fn finished_action_goes_back_to_the_animation_it_interrupted() {
+        let mut world = World::new();
+        let mut animator = Animator::default();
+        System::setup(&mut animator, &mut world);
+        world.insert(GameClock::default());
+        let mut finished_reader = world.fetch_mut::<EventChannel<AnimationFinished>>().register_reader();
+
+        let walk = clip(&[0, 16, 32, 48], Playback::Loop);
+        let mut states = AnimationStates::new(walk.clone(), Direction::Down);
+        states.clips.insert(AnimationState::Attack, clip(&[100, 116], Playback::Once));
+        let entity = world.create_entity()
+            .with(Velocity(Vector::new(0.0, 10.0)))
+            .with(states)
+            .with(sprite(-1))
+            .build();
+
+        // Walk for a couple of frames and then attack
+        run_at(&mut animator, &mut world, 0);
+        run_at(&mut animator, &mut world, 100);
+        run_at(&mut animator, &mut world, 200);
+        assert_eq!(world.read_storage::<Sprite>().get(entity), Some(&sprite(32)));
+        world.write_resource::<EventChannel<AnimationTrigger>>()
+            .single_write(AnimationTrigger {entity, state: AnimationState::Attack});
+        run_at(&mut animator, &mut world, 250);
+        {
+            let animations = world.read_storage::<Animation>();
+            let attack = animations.get(entity).unwrap();
+            assert_eq!(attack.playback, Playback::Once);
+            let previous = attack.previous.as_ref().expect("attack should remember the walk it interrupted");
+            assert_eq!(previous.current_frame, 2);
+        }
+        assert_eq!(world.read_storage::<Sprite>().get(entity), Some(&sprite(100)));
+
+        run_at(&mut animator, &mut world, 350);
+        assert_eq!(world.read_storage::<Sprite>().get(entity), Some(&sprite(116)));
+        assert_eq!(world.fetch::<EventChannel<AnimationFinished>>().read(&mut finished_reader).count(), 0);
+
+        // On the same step that the attack finishes, the walk picks up where it left off
+        run_at(&mut animator, &mut world, 450);
+        let finished: Vec<_> = world.fetch::<EventChannel<AnimationFinished>>().read(&mut finished_reader).copied().collect();
+        assert_eq!(finished, vec![AnimationFinished {entity}]);
+        let animations = world.read_storage::<Animation>();
+        let restored = animations.get(entity).unwrap();
+        assert_eq!(restored.frames, walk.down.frames);
+        assert_eq!(restored.current_frame, 2);
+        assert!(restored.previous.is_none());
+        drop(animations);
+        assert_eq!(world.read_storage::<Sprite>().get(entity), Some(&sprite(32)));
+        assert_eq!(world.read_storage::<AnimationStates>().get(entity).map(|states| states.state), Some(AnimationState::Walk));
+
+        // The walk keeps going from there instead of starting over
+        for (millis, frame) in [(550, 3), (650, 0), (750, 1), (850, 2)].iter().copied() {
+            run_at(&mut animator, &mut world, millis);
+            assert_eq!(world.read_storage::<Animation>().get(entity).map(|anim| anim.current_frame), Some(frame));
         }
+        assert_eq!(world.read_storage::<Sprite>().get(entity), Some(&sprite(32)));
     }
 }
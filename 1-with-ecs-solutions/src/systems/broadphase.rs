@@ -0,0 +1,28 @@
+use specs::{System, SystemData, Entities, ReadStorage, WriteExpect, Join, World, prelude::ResourceId};
+
+use crate::spatial_hash::SpatialHash;
+use crate::components::BoundingBox;
+
+pub struct Broadphase;
+
+/// Data from the world required by the system
+#[derive(SystemData)]
+pub struct BroadphaseData<'a> {
+    entities: Entities<'a>,
+    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    spatial_hash: WriteExpect<'a, SpatialHash>,
+}
+
+impl<'a> System<'a> for Broadphase {
+    type SystemData = BroadphaseData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let BroadphaseData {entities, bounding_boxes, mut spatial_hash} = data;
+
+        // Rebuild the spatial hash from scratch since almost everything moves on every step
+        spatial_hash.clear();
+        for (entity, &BoundingBox(bounds)) in (&*entities, &bounding_boxes).join() {
+            spatial_hash.insert(entity, bounds);
+        }
+    }
+}
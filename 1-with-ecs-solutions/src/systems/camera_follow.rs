@@ -0,0 +1,30 @@
+use specs::{System, SystemData, ReadStorage, ReadExpect, WriteExpect, World, prelude::ResourceId};
+
+use crate::camera::Camera;
+use crate::resources::TimeDelta;
+use crate::components::Position;
+
+/// Moves the camera to follow its target
+pub struct CameraFollow;
+
+/// Data from the world required by the system
+#[derive(SystemData)]
+pub struct CameraFollowData<'a> {
+    positions: ReadStorage<'a, Position>,
+    camera: WriteExpect<'a, Camera>,
+    time_delta: ReadExpect<'a, TimeDelta>,
+}
+
+impl<'a> System<'a> for CameraFollow {
+    type SystemData = CameraFollowData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let CameraFollowData {positions, mut camera, time_delta} = data;
+        let TimeDelta(time_elapsed) = *time_delta;
+
+        let target = camera.target
+            .and_then(|target| positions.get(target))
+            .map(|&Position(target_pos)| target_pos);
+        camera.update(target, time_elapsed);
+    }
+}
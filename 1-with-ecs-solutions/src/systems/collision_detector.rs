@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use sdl2::rect::Rect;
+use specs::{System, SystemData, Entities, Entity, ReadStorage, ReadExpect, Write, Join, World, prelude::ResourceId};
+use specs::shrev::EventChannel;
+
+use crate::spatial_hash::SpatialHash;
+use crate::resources::{Collision, CollisionPhase};
+use crate::components::{BoundingBox, CollisionFilter};
+
+/// Finds every pair of entities with overlapping bounding boxes and publishes a `Collision` event
+/// for each of them. Systems that need to react to collisions should read those events instead of
+/// checking for intersections themselves.
+#[derive(Default)]
+pub struct CollisionDetector {
+    /// The overlap of every pair of entities that were touching as of the last step
+    ///
+    /// A BTreeMap is used so that exit events are always published in the same order.
+    touching: BTreeMap<(Entity, Entity), Rect>,
+}
+
+/// Data from the world required by the system
+#[derive(SystemData)]
+pub struct CollisionDetectorData<'a> {
+    entities: Entities<'a>,
+    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    filters: ReadStorage<'a, CollisionFilter>,
+    spatial_hash: ReadExpect<'a, SpatialHash>,
+    collisions: Write<'a, EventChannel<Collision>>,
+}
+
+impl<'a> System<'a> for CollisionDetector {
+    type SystemData = CollisionDetectorData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let CollisionDetectorData {entities, bounding_boxes, filters, spatial_hash, mut collisions} = data;
+
+        let mut touching = BTreeMap::new();
+        for (entity, &BoundingBox(bounds)) in (&*entities, &bounding_boxes).join() {
+            for (other, other_bounds) in spatial_hash.query(bounds) {
+                // Each pair will be found twice (once from each entity), so only keep one of them.
+                // This also skips the entity finding itself.
+                if other <= entity {
+                    continue;
+                }
+                // Ignore entities that aren't allowed to collide with each other
+                if !CollisionFilter::of(&filters, entity).collides_with(CollisionFilter::of(&filters, other)) {
+                    continue;
+                }
+
+                if let Some(overlap) = bounds.intersection(other_bounds) {
+                    let entities = (entity, other);
+                    let phase = if self.touching.contains_key(&entities) {
+                        CollisionPhase::Stay
+                    } else {
+                        CollisionPhase::Enter
+                    };
+
+                    collisions.single_write(Collision {phase, entities, overlap});
+                    touching.insert(entities, overlap);
+                }
+            }
+        }
+
+        // Any pair that was touching before but wasn't found this time has stopped touching
+        for (&entities, &overlap) in &self.touching {
+            if !touching.contains_key(&entities) {
+                collisions.single_write(Collision {phase: CollisionPhase::Exit, entities, overlap});
+            }
+        }
+
+        self.touching = touching;
+    }
+}
@@ -0,0 +1,42 @@
+use specs::{System, SystemData, Entities, ReadStorage, Read, World, ReaderId, prelude::ResourceId};
+use specs::shrev::EventChannel;
+
+use crate::resources::AnimationFinished;
+use crate::components::DespawnAfterAnimation;
+
+/// Deletes entities with `DespawnAfterAnimation` once their animation has finished
+#[derive(Default)]
+pub struct Despawner {
+    /// Used to read the animation events published since the last time this system ran
+    animation_reader: Option<ReaderId<AnimationFinished>>,
+}
+
+/// Data from the world required by the system
+#[derive(SystemData)]
+pub struct DespawnerData<'a> {
+    entities: Entities<'a>,
+    despawn_after_animation: ReadStorage<'a, DespawnAfterAnimation>,
+    animation_finished: Read<'a, EventChannel<AnimationFinished>>,
+}
+
+impl<'a> System<'a> for Despawner {
+    type SystemData = DespawnerData<'a>;
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.animation_reader = Some(world.fetch_mut::<EventChannel<AnimationFinished>>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData) {
+        let DespawnerData {entities, despawn_after_animation, animation_finished} = data;
+        let animation_reader = self.animation_reader.as_mut()
+            .expect("bug: Despawner was not setup");
+
+        for &AnimationFinished {entity} in animation_finished.read(animation_reader) {
+            if despawn_after_animation.contains(entity) {
+                // The entity is removed from the world once the current step is over
+                entities.delete(entity).expect("bug: finished animation should belong to a live entity");
+            }
+        }
+    }
+}
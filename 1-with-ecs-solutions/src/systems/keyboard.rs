@@ -1,7 +1,8 @@
-use specs::{System, SystemData, Read, ReadStorage, WriteStorage, Join, World, prelude::ResourceId};
+use specs::{System, SystemData, ReadExpect, ReadStorage, WriteStorage, Join, World, prelude::ResourceId};
 
-use crate::resources::KeyboardEvent;
-use crate::components::{Player, Velocity};
+use crate::vector::Vector;
+use crate::resources::HeldActions;
+use crate::components::{Player, Velocity, Dead};
 
 pub struct Keyboard;
 
@@ -9,33 +10,36 @@ pub struct Keyboard;
 #[derive(SystemData)]
 pub struct KeyboardData<'a> {
     players: ReadStorage<'a, Player>,
+    dead: ReadStorage<'a, Dead>,
     velocities: WriteStorage<'a, Velocity>,
-    keyboard_event: Read<'a, Option<KeyboardEvent>>,
+    held_actions: ReadExpect<'a, HeldActions>,
 }
 
 impl<'a> System<'a> for Keyboard {
     type SystemData = KeyboardData<'a>;
 
     fn run(&mut self, data: Self::SystemData) {
-        let KeyboardData {players, mut velocities, keyboard_event} = data;
+        let KeyboardData {players, dead, mut velocities, held_actions} = data;
 
-        use KeyboardEvent::*;
-        match *keyboard_event {
-            // Instruct player to move in the given direction
-            Some(MoveInDirection(direction)) => {
-                for (&Player {movement_speed}, velocity) in (&players, &mut velocities).join() {
-                    velocity.speed = movement_speed;
-                    velocity.direction = direction;
+        match held_actions.direction() {
+            // Instruct player to move in the direction of the held keys
+            Some(direction) => {
+                for (&Player {movement_speed}, Velocity(velocity), ()) in (&players, &mut velocities, !&dead).join() {
+                    // The direction vector has a length of 1, so diagonal movement is no faster
+                    *velocity = direction.into_vector() * movement_speed;
                 }
             },
-            // Instruct player to stop (but preserve the direction)
-            Some(Stop) => {
-                for (_, velocity) in (&players, &mut velocities).join() {
-                    velocity.speed = 0;
+            // Instruct player to stop
+            None => {
+                for (_, Velocity(velocity)) in (&players, &mut velocities).join() {
+                    *velocity = Vector::default();
                 }
             },
-            // Do nothing if there is no event to process
-            None => {},
+        }
+
+        // Players that have died can no longer move
+        for (_, Velocity(velocity), _) in (&players, &mut velocities, &dead).join() {
+            *velocity = Vector::default();
         }
     }
 }
@@ -1,17 +1,118 @@
 use sdl2::rect::Rect;
-use specs::{System, SystemData, ReadExpect, ReadStorage, WriteStorage, Join, World, prelude::ResourceId};
+use specs::{System, SystemData, Entities, Entity, ReadExpect, ReadStorage, WriteStorage, Join, World, prelude::ResourceId};
 
+use crate::vector::Vector;
+use crate::spatial_hash::SpatialHash;
 use crate::resources::TimeDelta;
-use crate::components::{BoundingBox, Velocity};
+use crate::components::{Position, PreviousPosition, Collider, CollisionFilter, BoundingBox, Velocity, Obstacle};
 
 pub struct Movement {
-    pub world_bounds: Rect,
+    world_bounds: Rect,
+    /// The bounding boxes of every obstacle, rebuilt on every step
+    ///
+    /// This can't use the `SpatialHash` resource because that is only rebuilt after all of the
+    /// movement for a step has taken place.
+    obstacles: SpatialHash,
+}
+
+impl Movement {
+    /// Creates a movement system that keeps every entity within `world_bounds`
+    pub fn new(world_bounds: Rect) -> Self {
+        Self {
+            world_bounds,
+            obstacles: SpatialHash::default(),
+        }
+    }
+
+    /// Returns how much of the given move along a single axis an entity can make before it runs
+    /// into the edge of the world or an obstacle
+    ///
+    /// Rather than only checking where the entity ends up, this checks everything in between so
+    /// that the entity stops flush against whatever it hits first, no matter how fast it is
+    /// going. Obstacles that the entity already overlaps are ignored so it can walk out of them,
+    /// as are any obstacles that the entity's `CollisionFilter` doesn't allow it to collide with.
+    fn sweep(
+        &self,
+        entity: Entity,
+        center: Vector,
+        size: Vector,
+        axis_move: Vector,
+        filters: &ReadStorage<CollisionFilter>,
+    ) -> Vector {
+        let min = center - size * 0.5;
+        let max = center + size * 0.5;
+
+        // Only obstacles in the area covered by the entity during the move could possibly be hit.
+        // An extra pixel on each side accounts for any rounding.
+        let area_min = Vector::new(min.x + axis_move.x.min(0.0), min.y + axis_move.y.min(0.0));
+        let area_max = Vector::new(max.x + axis_move.x.max(0.0), max.y + axis_move.y.max(0.0));
+        let area = Rect::new(
+            area_min.x.floor() as i32 - 1,
+            area_min.y.floor() as i32 - 1,
+            (area_max.x - area_min.x).ceil() as u32 + 3,
+            (area_max.y - area_min.y).ceil() as u32 + 3,
+        );
+        let entity_filter = CollisionFilter::of(filters, entity);
+        let obstacles = self.obstacles.query(area).into_iter()
+            // An obstacle can't get in the way of itself
+            .filter(|&(obstacle, _)| obstacle != entity)
+            .filter(|&(obstacle, _)| entity_filter.collides_with(CollisionFilter::of(filters, obstacle)))
+            .map(|(_, obstacle_bounds)| obstacle_bounds);
+
+        let world = self.world_bounds;
+        if axis_move.x != 0.0 {
+            // Only obstacles level with the entity can be hit when moving horizontally
+            let obstacles = obstacles
+                .filter(|obstacle| (obstacle.top() as f64) < max.y && (obstacle.bottom() as f64) > min.y)
+                .map(|obstacle| (obstacle.left() as f64, obstacle.right() as f64));
+            let limits = (world.left() as f64, world.right() as f64);
+            Vector::new(sweep_axis(min.x, max.x, axis_move.x, limits, obstacles), 0.0)
+        } else {
+            // Only obstacles in line with the entity can be hit when moving vertically
+            let obstacles = obstacles
+                .filter(|obstacle| (obstacle.left() as f64) < max.x && (obstacle.right() as f64) > min.x)
+                .map(|obstacle| (obstacle.top() as f64, obstacle.bottom() as f64));
+            let limits = (world.top() as f64, world.bottom() as f64);
+            Vector::new(0.0, sweep_axis(min.y, max.y, axis_move.y, limits, obstacles))
+        }
+    }
+}
+
+/// Returns how far something spanning from `min` to `max` along an axis can move by `distance`
+/// along that axis without leaving `limits` or passing into any of the `obstacles` (each given as
+/// the span they cover along the same axis)
+fn sweep_axis(
+    min: f64,
+    max: f64,
+    distance: f64,
+    (lower_limit, upper_limit): (f64, f64),
+    obstacles: impl Iterator<Item=(f64, f64)>,
+) -> f64 {
+    if distance > 0.0 {
+        let allowed = obstacles
+            // Only obstacles ahead of the entity are in the way
+            .filter(|&(obstacle_min, _)| obstacle_min >= max)
+            .fold(upper_limit - max, |allowed, (obstacle_min, _)| allowed.min(obstacle_min - max));
+        // Never move backwards, even if the entity is somehow already past a limit
+        distance.min(allowed.max(0.0))
+    } else {
+        let allowed = obstacles
+            .filter(|&(_, obstacle_max)| obstacle_max <= min)
+            .fold(lower_limit - min, |allowed, (_, obstacle_max)| allowed.max(obstacle_max - min));
+        distance.max(allowed.min(0.0))
+    }
 }
 
 /// Data from the world required by the system
 #[derive(SystemData)]
 pub struct MovementData<'a> {
+    entities: Entities<'a>,
     velocities: ReadStorage<'a, Velocity>,
+    obstacles: ReadStorage<'a, Obstacle>,
+    colliders: ReadStorage<'a, Collider>,
+    filters: ReadStorage<'a, CollisionFilter>,
+    positions: WriteStorage<'a, Position>,
+    previous_positions: WriteStorage<'a, PreviousPosition>,
     bounding_boxes: WriteStorage<'a, BoundingBox>,
     time_delta: ReadExpect<'a, TimeDelta>,
 }
@@ -20,31 +121,135 @@ impl<'a> System<'a> for Movement {
     type SystemData = MovementData<'a>;
 
     fn run(&mut self, data: Self::SystemData) {
-        let MovementData {velocities, mut bounding_boxes, time_delta} = data;
+        let MovementData {
+            entities,
+            velocities,
+            obstacles,
+            colliders,
+            filters,
+            mut positions,
+            mut previous_positions,
+            mut bounding_boxes,
+            time_delta,
+        } = data;
         let TimeDelta(time_elapsed) = *time_delta;
 
-        for (&Velocity {speed, direction}, BoundingBox(bounds)) in (&velocities, &mut bounding_boxes).join() {
+        self.obstacles.clear();
+        for (entity, _, &BoundingBox(bounds)) in (&*entities, &obstacles, &bounding_boxes).join() {
+            self.obstacles.insert(entity, bounds);
+        }
+
+        for (entity, &Velocity(velocity), collider, Position(position), bounds) in (&*entities, &velocities, colliders.maybe(), &mut positions, (&mut bounding_boxes).maybe()).join() {
+            // Remember where the entity was before this step so the renderer can interpolate
+            // between the two positions. This needs to happen even if the entity isn't moving or
+            // else it would be drawn somewhere between its current position and some stale one.
+            previous_positions.insert(entity, PreviousPosition(*position))
+                .expect("failed to update previous position");
+
             // No need to update position if not moving
-            if speed == 0 {
+            if velocity.is_zero() {
                 continue;
             }
 
-            // Compute the distance (in pixels) traversed during the time elapsed.
-            // Notes:
-            // * There are 1000000 microseconds in 1 second.
-            // * We do the division at the end so rounding due to integer division occurs last.
-            // * The conversion to i32 is only safe because we assume that the number of
-            //   microseconds in time_elapsed fits within the range of i32.
-            let distance = speed * time_elapsed.as_micros() as i32 / 1_000_000;
-
-            // Move in the current direction
-            let new_pos = bounds.center() + direction.into_point() * distance;
-            let new_bounds = Rect::from_center(new_pos, bounds.width(), bounds.height());
-
-            // Disallow entities from leaving the window
-            if self.world_bounds.contains_rect(new_bounds) {
-                *bounds = new_bounds;
+            // Compute the distance (in pixels) traversed during the time elapsed. This is usually
+            // only a fraction of a pixel, so it is important that the position isn't rounded.
+            let displacement = velocity * time_elapsed.as_secs_f64();
+
+            // Without a collider, nothing can get in the way
+            let collider = match collider {
+                Some(collider) => collider,
+                None => {
+                    *position = *position + displacement;
+                    continue;
+                },
+            };
+            let size = Vector::new(collider.width as f64, collider.height as f64);
+
+            // Move along each axis separately. That way, an entity moving diagonally into a wall
+            // or obstacle is only stopped along the blocked axis and slides along the other one
+            // instead of getting stuck.
+            let axis_moves = [Vector::new(displacement.x, 0.0), Vector::new(0.0, displacement.y)];
+            for &axis_move in &axis_moves {
+                if axis_move.is_zero() {
+                    continue;
+                }
+
+                *position = *position + self.sweep(entity, *position + collider.offset, size, axis_move, &filters);
+            }
+
+            if let Some(BoundingBox(bounds)) = bounds {
+                *bounds = collider.bounds_at(*position);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::iter;
+    use std::time::Duration;
+
+    use specs::{WorldExt, Builder, RunNow};
+
+    const LIMITS: (f64, f64) = (-100.0, 100.0);
+
+    #[test]
+    fn moves_freely_without_obstacles() {
+        assert_eq!(sweep_axis(0.0, 10.0, 25.5, LIMITS, iter::empty()), 25.5);
+        assert_eq!(sweep_axis(0.0, 10.0, -25.5, LIMITS, iter::empty()), -25.5);
+    }
+
+    #[test]
+    fn stops_flush_against_obstacles() {
+        let obstacles = || vec![(30.0, 40.0), (-40.0, -20.0), (60.0, 70.0)].into_iter();
+        // However far the move goes, the entity stops at the nearest obstacle ahead of it
+        assert_eq!(sweep_axis(0.0, 10.0, 15.0, LIMITS, obstacles()), 15.0);
+        assert_eq!(sweep_axis(0.0, 10.0, 20.0, LIMITS, obstacles()), 20.0);
+        assert_eq!(sweep_axis(0.0, 10.0, 500.0, LIMITS, obstacles()), 20.0);
+        assert_eq!(sweep_axis(0.0, 10.0, -500.0, LIMITS, obstacles()), -20.0);
+    }
+
+    #[test]
+    fn stops_flush_against_world_limits() {
+        assert_eq!(sweep_axis(80.0, 90.0, 25.0, LIMITS, iter::empty()), 10.0);
+        assert_eq!(sweep_axis(-90.0, -80.0, -25.0, LIMITS, iter::empty()), -10.0);
+    }
+
+    #[test]
+    fn ignores_overlapping_obstacles() {
+        // The entity is already inside this obstacle, so it can walk out in either direction
+        let obstacles = || iter::once((5.0, 15.0));
+        assert_eq!(sweep_axis(0.0, 10.0, 30.0, LIMITS, obstacles()), 30.0);
+        assert_eq!(sweep_axis(0.0, 10.0, -30.0, LIMITS, obstacles()), -30.0);
+    }
+
+    #[test]
+    fn never_moves_backwards() {
+        // Already touching an obstacle or limit
+        assert_eq!(sweep_axis(0.0, 10.0, 5.0, LIMITS, iter::once((10.0, 20.0))), 0.0);
+        assert_eq!(sweep_axis(-100.0, -90.0, -5.0, LIMITS, iter::empty()), 0.0);
+        // Somehow already past a limit
+        assert_eq!(sweep_axis(95.0, 105.0, 5.0, LIMITS, iter::empty()), 0.0);
+        assert_eq!(sweep_axis(-105.0, -95.0, -5.0, LIMITS, iter::empty()), 0.0);
+    }
+
+    #[test]
+    fn moves_entities_without_colliders() {
+        let mut world = World::new();
+        let mut movement = Movement::new(Rect::from_center((0, 0), 100, 100));
+        System::setup(&mut movement, &mut world);
+        world.insert(TimeDelta(Duration::from_millis(500)));
+        let entity = world.create_entity()
+            .with(Position(Vector::new(10.0, 20.0)))
+            .with(Velocity(Vector::new(40.0, 0.0)))
+            .build();
+
+        movement.run_now(&world);
+
+        // Nothing gets in the way, not even the edge of the world
+        assert_eq!(world.read_storage::<Position>().get(entity).map(|pos| pos.0), Some(Vector::new(30.0, 20.0)));
+        assert_eq!(world.read_storage::<PreviousPosition>().get(entity).map(|pos| pos.0), Some(Vector::new(10.0, 20.0)));
+    }
+}
@@ -0,0 +1,47 @@
+use specs::{System, SystemData, Entities, ReadExpect, WriteExpect, ReadStorage, WriteStorage, Join, LazyUpdate, Read, World, prelude::ResourceId};
+
+use crate::prefab::{Prefabs, Overrides};
+use crate::resources::{GameClock, GameRng};
+use crate::components::{Position, SpawnPoint};
+
+pub struct Spawning;
+
+/// Data from the world required by the system
+#[derive(SystemData)]
+pub struct SpawningData<'a> {
+    entities: Entities<'a>,
+    spawn_points: WriteStorage<'a, SpawnPoint>,
+    positions: ReadStorage<'a, Position>,
+    prefabs: ReadExpect<'a, Prefabs>,
+    lazy: Read<'a, LazyUpdate>,
+    clock: ReadExpect<'a, GameClock>,
+    rng: WriteExpect<'a, GameRng>,
+}
+
+impl<'a> System<'a> for Spawning {
+    type SystemData = SpawningData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let SpawningData {entities, mut spawn_points, positions, prefabs, lazy, clock, mut rng} = data;
+        let GameRng(rng) = &mut *rng;
+        let now = clock.now();
+
+        for (spawn_point, &Position(position)) in (&mut spawn_points, &positions).join() {
+            if spawn_point.remaining == 0 || now - spawn_point.timer < spawn_point.delay {
+                continue;
+            }
+
+            let overrides = Overrides {position, ..Overrides::default()};
+            // The new entity is added to the world once the current step is over
+            if let Err(err) = prefabs.spawn_lazy(&entities, &lazy, &spawn_point.prefab, &overrides, now, rng) {
+                // Stop using this spawn point rather than ending the game or retrying every step
+                eprintln!("unable to spawn '{}' from a spawn point: {}", spawn_point.prefab, err);
+                spawn_point.remaining = 0;
+                continue;
+            }
+
+            spawn_point.remaining -= 1;
+            spawn_point.timer = now;
+        }
+    }
+}
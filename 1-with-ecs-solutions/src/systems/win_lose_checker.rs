@@ -1,9 +1,16 @@
-use specs::{System, SystemData, ReadStorage, WriteExpect, Join, World, prelude::ResourceId};
+use specs::{System, SystemData, ReadStorage, WriteStorage, Read, Write, WriteExpect, World, ReaderId, prelude::ResourceId};
+use specs::shrev::EventChannel;
 
-use crate::resources::GameStatus;
-use crate::components::{Player, Enemy, Goal, BoundingBox};
+use crate::resources::{GameStatus, Collision, CollisionPhase, AnimationTrigger, AnimationFinished};
+use crate::components::{Player, Enemy, Goal, Dead, AnimationStates, AnimationState};
 
-pub struct WinLoseChecker;
+#[derive(Default)]
+pub struct WinLoseChecker {
+    /// Used to read the collision events published since the last time this system ran
+    collision_reader: Option<ReaderId<Collision>>,
+    /// Used to read the animation events published since the last time this system ran
+    animation_reader: Option<ReaderId<AnimationFinished>>,
+}
 
 /// Data from the world required by the system
 #[derive(SystemData)]
@@ -11,32 +18,91 @@ pub struct WinLoseCheckerData<'a> {
     players: ReadStorage<'a, Player>,
     enemies: ReadStorage<'a, Enemy>,
     goals: ReadStorage<'a, Goal>,
-    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    dead: WriteStorage<'a, Dead>,
+    animation_states: ReadStorage<'a, AnimationStates>,
+    collisions: Read<'a, EventChannel<Collision>>,
+    animation_finished: Read<'a, EventChannel<AnimationFinished>>,
+    animation_triggers: Write<'a, EventChannel<AnimationTrigger>>,
     game_status: WriteExpect<'a, GameStatus>,
 }
 
 impl<'a> System<'a> for WinLoseChecker {
     type SystemData = WinLoseCheckerData<'a>;
 
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.collision_reader = Some(world.fetch_mut::<EventChannel<Collision>>().register_reader());
+        self.animation_reader = Some(world.fetch_mut::<EventChannel<AnimationFinished>>().register_reader());
+    }
+
     fn run(&mut self, data: Self::SystemData) {
-        let WinLoseCheckerData {players, enemies, goals, bounding_boxes, mut game_status} = data;
+        let WinLoseCheckerData {
+            players,
+            enemies,
+            goals,
+            mut dead,
+            animation_states,
+            collisions,
+            animation_finished,
+            mut animation_triggers,
+            mut game_status,
+        } = data;
+        let collision_reader = self.collision_reader.as_mut()
+            .expect("bug: WinLoseChecker was not setup");
+        let animation_reader = self.animation_reader.as_mut()
+            .expect("bug: WinLoseChecker was not setup");
+
+        // A player that was caught loses once their death animation has finished
+        for &AnimationFinished {entity} in animation_finished.read(animation_reader) {
+            if players.contains(entity) && dead.contains(entity) {
+                *game_status = GameStatus::Lose;
+                return;
+            }
+        }
+
+        let mut reached_goal = false;
+        for &Collision {phase, entities: (entity_a, entity_b), ..} in collisions.read(collision_reader) {
+            // Only care about entities that are currently touching
+            if phase == CollisionPhase::Exit {
+                continue;
+            }
 
-        for (_, BoundingBox(player_bounds)) in (&players, &bounding_boxes).join() {
-            for (_, &BoundingBox(enemy_bounds)) in (&enemies, &bounding_boxes).join() {
-                // If the player collides with any enemies, they lose
-                if player_bounds.has_intersection(enemy_bounds) {
+            // Find the entity that the player is touching
+            let (player, other) = if players.contains(entity_a) {
+                (entity_a, entity_b)
+            } else if players.contains(entity_b) {
+                (entity_b, entity_a)
+            } else {
+                continue;
+            };
+
+            // A player that was caught can't be caught again or reach the goal
+            if dead.contains(player) {
+                continue;
+            }
+
+            // If the player collides with any enemies, they die and lose
+            if enemies.contains(other) {
+                dead.insert(player, Dead).expect("bug: player should be alive");
+                animation_triggers.single_write(AnimationTrigger {entity: other, state: AnimationState::Attack});
+
+                // Without a death animation to wait for, the game is lost right away
+                let has_death_animation = animation_states.get(player)
+                    .is_some_and(|states| states.clips.contains_key(&AnimationState::Dead));
+                if !has_death_animation {
                     *game_status = GameStatus::Lose;
-                    return;
                 }
+                return;
             }
 
-            for (_, &BoundingBox(goal_bounds)) in (&goals, &bounding_boxes).join() {
-                // If the player reaches the goal, they win
-                if player_bounds.has_intersection(goal_bounds) {
-                    *game_status = GameStatus::Win;
-                    return;
-                }
+            // If the player reaches the goal, they win (unless they also hit an enemy)
+            if goals.contains(other) {
+                reached_goal = true;
             }
         }
+
+        if reached_goal {
+            *game_status = GameStatus::Win;
+        }
     }
 }
@@ -0,0 +1,507 @@
+//! Maps are authored in the Tiled map editor (https://www.mapeditor.org) and loaded from its JSON
+//! export (File > Export As... > JSON map files).
+//!
+//! * Tile layers are drawn beneath every entity, in the same order as they appear in Tiled.
+//! * Objects in object layers are turned into entities using the prefab named by their type
+//!   (called "class" in newer versions of Tiled), e.g. `bardo` or `reaper`. Objects without a type
+//!   are ignored so that they can be used for notes.
+//! * Any tile with a custom `solid` property set to `true` (in the tileset editor) blocks movement
+//!   by being covered with entities made from the `solid` prefab.
+//!   This applies even to tiles in hidden layers, so a hidden layer can be used to add collision
+//!   without drawing anything.
+//!
+//! Only orthogonal, finite maps with embedded tilesets and CSV tile layer data are supported. These
+//! are all settings that can be changed in Tiled if a map doesn't load. Flipped and rotated tiles
+//! are drawn without their flip or rotation.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::rect::Rect;
+use serde::Deserialize;
+
+use crate::vector::Vector;
+use crate::resources::{TileMap, Tile};
+
+/// The name of the prefab that solid tiles are covered with
+pub const SOLID_PREFAB: &str = "solid";
+
+/// The bits of a tile ID used by Tiled to store whether the tile is flipped
+const FLIP_FLAGS: u32 = 0xE000_0000;
+
+/// A map loaded from a file exported by Tiled
+#[derive(Debug)]
+pub struct Map {
+    /// The width of the map in tiles
+    width: u32,
+    /// The height of the map in tiles
+    height: u32,
+    /// The width (in pixels) of each cell in the map's grid
+    tile_width: u32,
+    /// The height (in pixels) of each cell in the map's grid
+    tile_height: u32,
+    /// The tile layers, in the order they are drawn
+    layers: Vec<TileLayer>,
+    tilesets: Vec<Tileset>,
+    spawns: Vec<Spawn>,
+}
+
+#[derive(Debug)]
+struct TileLayer {
+    /// Hidden layers are not drawn, but their solid tiles still block movement
+    visible: bool,
+    /// The tile ID of each cell, stored row by row with 0 for an empty cell
+    tile_ids: Vec<u32>,
+}
+
+#[derive(Debug)]
+struct Tileset {
+    /// The tile ID of the first tile in this tileset
+    first_id: u32,
+    /// The path to the image containing the tiles
+    image: PathBuf,
+    /// The number of tiles in each row of the image
+    columns: u32,
+    tile_count: u32,
+    tile_width: u32,
+    tile_height: u32,
+    /// The space (in pixels) around the tiles at the edge of the image
+    margin: u32,
+    /// The space (in pixels) between each tile in the image
+    spacing: u32,
+    /// The IDs (relative to `first_id`) of the tiles that block movement
+    solid_tiles: Vec<u32>,
+}
+
+/// An entity to create from an object in the map
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spawn {
+    /// The ID of the object in the map that this came from
+    pub object_id: u32,
+    /// The name of the prefab to create the entity from
+    pub prefab: String,
+    /// The position of the entity in world coordinates
+    pub position: Vector,
+}
+
+impl Map {
+    /// Loads a map from the given JSON file exported by Tiled
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("unable to read map '{}': {}", path.display(), err))?;
+        let map_file: MapFile = serde_json::from_str(&json)
+            .map_err(|err| format!("invalid map '{}': {}", path.display(), err))?;
+
+        // Images are referenced relative to the map file
+        let map_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::from_file(map_file, map_dir)
+            .map_err(|err| format!("invalid map '{}': {}", path.display(), err))
+    }
+
+    fn from_file(map_file: MapFile, map_dir: &Path) -> Result<Self, String> {
+        let MapFile {orientation, infinite, width, height, tilewidth, tileheight, layers, tilesets} = map_file;
+        if orientation != "orthogonal" {
+            return Err(format!("unsupported orientation '{}', only orthogonal maps are supported", orientation));
+        }
+        if infinite {
+            return Err("infinite maps are not supported".to_string());
+        }
+
+        let tilesets = tilesets.into_iter()
+            .map(|tileset| Tileset::from_file(tileset, map_dir))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut map = Self {
+            width,
+            height,
+            tile_width: tilewidth,
+            tile_height: tileheight,
+            layers: Vec::new(),
+            tilesets,
+            spawns: Vec::new(),
+        };
+
+        let mut objects = Vec::new();
+        for layer in flatten_layers(layers) {
+            match layer {
+                LayerFile::TileLayer {name, visible, encoding, data} => {
+                    if let Some(encoding) = encoding.filter(|encoding| encoding != "csv") {
+                        return Err(format!("layer '{}' has {} encoded tile data, \
+                            set its tile layer format to CSV in the map properties", name, encoding));
+                    }
+                    let data = data.ok_or_else(|| format!("layer '{}' has no tile data", name))?;
+                    let tile_ids: Vec<u32> = serde_json::from_value(data)
+                        .map_err(|err| format!("layer '{}' has invalid tile data: {}", name, err))?;
+                    if tile_ids.len() != (width * height) as usize {
+                        return Err(format!("layer '{}' has {} tiles, expected {}", name, tile_ids.len(), width * height));
+                    }
+                    for &tile_id in &tile_ids {
+                        if tile_id != 0 && map.tileset_for(tile_id).is_none() {
+                            return Err(format!("layer '{}' uses tile {} which is not in any tileset", name, tile_id & !FLIP_FLAGS));
+                        }
+                    }
+
+                    map.layers.push(TileLayer {visible, tile_ids});
+                },
+                LayerFile::ObjectGroup {objects: layer_objects} => objects.extend(layer_objects),
+                LayerFile::Group {..} => unreachable!("bug: groups should have been flattened"),
+                LayerFile::Other => {},
+            }
+        }
+
+        map.spawns = objects.into_iter().filter_map(|object| map.spawn_for(object)).collect();
+
+        Ok(map)
+    }
+
+    /// The area covered by the map in world coordinates, centered on the origin
+    pub fn bounds(&self) -> Rect {
+        Rect::from_center((0, 0), self.width * self.tile_width, self.height * self.tile_height)
+    }
+
+    /// The images used by the tilesets of this map
+    ///
+    /// These need to be loaded as textures in this order so that `tile_map` can refer to them.
+    pub fn tileset_images(&self) -> impl Iterator<Item=&Path> {
+        self.tilesets.iter().map(|tileset| tileset.image.as_path())
+    }
+
+    /// Returns every tile to draw, given the ID of the texture loaded for the first image in
+    /// `tileset_images` (the others must follow it in order)
+    pub fn tile_map(&self, first_texture_id: usize) -> TileMap {
+        let mut tiles = Vec::new();
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            for (cell, &tile_id) in layer.tile_ids.iter().enumerate() {
+                let (tileset_index, tileset) = match self.tileset_for(tile_id) {
+                    Some(tileset) => tileset,
+                    // Empty cell
+                    None => continue,
+                };
+
+                let cell_bounds = self.cell_bounds(cell);
+                // Tiles that are bigger than the grid stick out from the top-left of their cell, so
+                // they are aligned with its bottom-left corner instead
+                let bounds = Rect::new(
+                    cell_bounds.left(),
+                    cell_bounds.bottom() - tileset.tile_height as i32,
+                    tileset.tile_width,
+                    tileset.tile_height,
+                );
+
+                tiles.push(Tile {
+                    texture_id: first_texture_id + tileset_index,
+                    region: tileset.region(tile_id),
+                    bounds,
+                });
+            }
+        }
+
+        TileMap {tiles}
+    }
+
+    /// The areas of the map (in world coordinates) covered by solid tiles
+    ///
+    /// Neighbouring solid tiles in the same row are combined into a single area so that a long wall
+    /// doesn't need a separate collider for every tile.
+    pub fn solid_areas(&self) -> Vec<Rect> {
+        let is_solid = |cell: usize| self.layers.iter().any(|layer| {
+            let tile_id = layer.tile_ids[cell];
+            self.tileset_for(tile_id).is_some_and(|(_, tileset)| tileset.is_solid(tile_id))
+        });
+
+        let mut areas = Vec::new();
+        for row in 0..self.height as usize {
+            let mut run_start = None;
+            // Going one past the end of the row closes any run that reaches the edge of the map
+            for col in 0..=self.width as usize {
+                let cell = row * self.width as usize + col;
+                let solid = col < self.width as usize && is_solid(cell);
+                match (run_start, solid) {
+                    (None, true) => run_start = Some(cell),
+                    (Some(start), false) => {
+                        let run_len = (cell - start) as u32;
+                        let first_cell = self.cell_bounds(start);
+                        areas.push(Rect::new(first_cell.x(), first_cell.y(), run_len * self.tile_width, self.tile_height));
+                        run_start = None;
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        areas
+    }
+
+    /// The entities placed in the object layers of the map
+    pub fn spawns(&self) -> &[Spawn] {
+        &self.spawns
+    }
+
+    /// Returns the index of the tileset containing the given tile and the tileset itself, or None
+    /// if the tile ID is 0 (an empty cell) or not part of any tileset
+    fn tileset_for(&self, tile_id: u32) -> Option<(usize, &Tileset)> {
+        let tile_id = tile_id & !FLIP_FLAGS;
+        self.tilesets.iter().enumerate()
+            .filter(|(_, tileset)| tile_id >= tileset.first_id && tile_id - tileset.first_id < tileset.tile_count)
+            .max_by_key(|(_, tileset)| tileset.first_id)
+    }
+
+    /// The area (in world coordinates) of the given cell in the map's grid
+    fn cell_bounds(&self, cell: usize) -> Rect {
+        let map_bounds = self.bounds();
+        let col = (cell % self.width as usize) as i32;
+        let row = (cell / self.width as usize) as i32;
+        Rect::new(
+            map_bounds.x() + col * self.tile_width as i32,
+            map_bounds.y() + row * self.tile_height as i32,
+            self.tile_width,
+            self.tile_height,
+        )
+    }
+
+    /// Converts a point in the map (measured in pixels from its top-left corner) to world
+    /// coordinates
+    fn to_world(&self, x: f64, y: f64) -> Vector {
+        let map_bounds = self.bounds();
+        Vector::new(map_bounds.x() as f64 + x, map_bounds.y() as f64 + y)
+    }
+
+    fn spawn_for(&self, object: ObjectFile) -> Option<Spawn> {
+        let ObjectFile {id, object_type, class, x, y, width, height, gid} = object;
+        // Tiled 1.9 renamed "type" to "class"
+        let prefab = if object_type.is_empty() { class } else { object_type };
+        if prefab.is_empty() {
+            return None;
+        }
+
+        // Entities are positioned where they touch the ground, which is the bottom center of the
+        // object. Tile objects are positioned by their bottom-left corner while every other kind of
+        // object is positioned by its top-left corner. Points have no size at all.
+        let position = match gid {
+            Some(_) => self.to_world(x + width / 2.0, y),
+            None => self.to_world(x + width / 2.0, y + height),
+        };
+
+        Some(Spawn {object_id: id, prefab, position})
+    }
+}
+
+impl Tileset {
+    fn from_file(tileset: TilesetFile, map_dir: &Path) -> Result<Self, String> {
+        let TilesetFile {name, firstgid, source, image, columns, tilecount, tilewidth, tileheight, margin, spacing, tiles} = tileset;
+        if let Some(source) = source {
+            return Err(format!("tileset '{}' is stored in a separate file, embed it in the map instead", source));
+        }
+        let image = image.ok_or_else(|| format!("tileset '{}' is not based on a single image", name))?;
+        if columns == 0 {
+            return Err(format!("tileset '{}' has no columns", name));
+        }
+
+        let solid_tiles = tiles.into_iter()
+            .filter(|tile| tile.properties.iter().any(|prop| prop.name == "solid" && prop.value == true))
+            .map(|tile| tile.id)
+            .collect();
+
+        Ok(Self {
+            first_id: firstgid,
+            image: map_dir.join(image),
+            columns,
+            tile_count: tilecount,
+            tile_width: tilewidth,
+            tile_height: tileheight,
+            margin,
+            spacing,
+            solid_tiles,
+        })
+    }
+
+    /// The region of the tileset image containing the given tile
+    fn region(&self, tile_id: u32) -> Rect {
+        let index = (tile_id & !FLIP_FLAGS) - self.first_id;
+        let col = index % self.columns;
+        let row = index / self.columns;
+        Rect::new(
+            (self.margin + col * (self.tile_width + self.spacing)) as i32,
+            (self.margin + row * (self.tile_height + self.spacing)) as i32,
+            self.tile_width,
+            self.tile_height,
+        )
+    }
+
+    fn is_solid(&self, tile_id: u32) -> bool {
+        self.solid_tiles.contains(&((tile_id & !FLIP_FLAGS) - self.first_id))
+    }
+}
+
+/// Replaces every group layer with the layers inside it
+fn flatten_layers(layers: Vec<LayerFile>) -> Vec<LayerFile> {
+    layers.into_iter().flat_map(|layer| match layer {
+        LayerFile::Group {layers} => flatten_layers(layers),
+        layer => vec![layer],
+    }).collect()
+}
+
+// The structures below mirror the parts of Tiled's JSON format that are used by the game. See
+// https://doc.mapeditor.org/en/stable/reference/json-map-format/ for the full format.
+
+#[derive(Debug, Deserialize)]
+struct MapFile {
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    layers: Vec<LayerFile>,
+    tilesets: Vec<TilesetFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum LayerFile {
+    #[serde(rename = "tilelayer")]
+    TileLayer {
+        name: String,
+        visible: bool,
+        /// Either "csv" (the tile IDs are listed in `data`) or "base64"
+        encoding: Option<String>,
+        data: Option<serde_json::Value>,
+    },
+    #[serde(rename = "objectgroup")]
+    ObjectGroup {
+        objects: Vec<ObjectFile>,
+    },
+    #[serde(rename = "group")]
+    Group {
+        layers: Vec<LayerFile>,
+    },
+    /// Image layers are not used by the game
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectFile {
+    id: u32,
+    #[serde(rename = "type", default)]
+    object_type: String,
+    #[serde(default)]
+    class: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    /// Only set for tile objects
+    gid: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TilesetFile {
+    #[serde(default)]
+    name: String,
+    firstgid: u32,
+    /// Only set for tilesets stored in a separate file, in which case none of the other fields are
+    source: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    /// Only tiles with custom properties are listed
+    #[serde(default)]
+    tiles: Vec<TileFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TileFile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<PropertyFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertyFile {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 map with 16x16 tiles, one tileset (tile 2 is solid) and the given layer data and
+    /// objects
+    fn map(data: &[u32], objects: &str) -> Map {
+        let json = format!(r#"{{
+            "orientation": "orthogonal",
+            "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16,
+            "layers": [
+                {{"type": "tilelayer", "name": "ground", "visible": true, "data": {:?}}},
+                {{"type": "objectgroup", "objects": [{}]}}
+            ],
+            "tilesets": [{{
+                "name": "tiles", "firstgid": 1, "image": "tiles.png",
+                "columns": 2, "tilecount": 4, "tilewidth": 16, "tileheight": 16,
+                "margin": 1, "spacing": 2,
+                "tiles": [{{"id": 1, "properties": [{{"name": "solid", "type": "bool", "value": true}}]}}]
+            }}]
+        }}"#, data, objects);
+        Map::from_file(serde_json::from_str(&json).unwrap(), Path::new("")).unwrap()
+    }
+
+    #[test]
+    fn ignores_flip_flags() {
+        let map = map(&[0; 6], "");
+        let flipped = 4 | 0x8000_0000 | 0x2000_0000;
+        let (index, tileset) = map.tileset_for(flipped).unwrap();
+        assert_eq!(index, 0);
+        // Tile 4 is the second tile in the second row
+        assert_eq!(tileset.region(flipped), Rect::new(19, 19, 16, 16));
+        assert_eq!(tileset.region(flipped), tileset.region(4));
+        assert!(tileset.is_solid(2 | 0x4000_0000));
+        assert!(map.tileset_for(0x8000_0000).is_none());
+    }
+
+    #[test]
+    fn solid_areas_do_not_continue_onto_the_next_row() {
+        // The run at the end of the first row must not be merged with the start of the second
+        let map = map(&[
+            1, 2, 2,
+            2, 1, 2 | 0x8000_0000,
+        ], "");
+        assert_eq!(map.solid_areas(), vec![
+            Rect::new(-8, -16, 32, 16),
+            Rect::new(-24, 0, 16, 16),
+            Rect::new(8, 0, 16, 16),
+        ]);
+    }
+
+    #[test]
+    fn objects_are_positioned_where_they_touch_the_ground() {
+        let map = map(&[0; 6], r#"
+            {"id": 1, "type": "tree", "x": 0, "y": 32, "width": 16, "height": 16, "gid": 1},
+            {"id": 2, "class": "bardo", "x": 24, "y": 8, "point": true},
+            {"id": 3, "type": "reaper", "x": 0, "y": 0, "width": 16, "height": 8},
+            {"id": 4, "x": 0, "y": 0}
+        "#);
+        assert_eq!(map.spawns(), &[
+            // Tile objects are positioned by their bottom-left corner
+            Spawn {object_id: 1, prefab: "tree".to_string(), position: Vector::new(-16.0, 16.0)},
+            Spawn {object_id: 2, prefab: "bardo".to_string(), position: Vector::new(0.0, -8.0)},
+            // Other objects are positioned by their top-left corner
+            Spawn {object_id: 3, prefab: "reaper".to_string(), position: Vector::new(-16.0, -8.0)},
+        ]);
+    }
+}
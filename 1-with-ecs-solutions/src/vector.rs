@@ -0,0 +1,66 @@
+use std::ops::{Add, Sub, Mul};
+
+use sdl2::rect::Point;
+
+/// A two-dimensional vector in world coordinates
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Vector {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Vector {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self {x, y}
+    }
+
+    /// Returns the pixel closest to the point at this vector
+    pub fn to_point(self) -> Point {
+        Point::new(self.x.round() as i32, self.y.round() as i32)
+    }
+
+    /// Returns true if both components of this vector are zero
+    pub fn is_zero(self) -> bool {
+        self.x == 0.0 && self.y == 0.0
+    }
+
+    /// Returns the length (magnitude) of this vector
+    pub fn length(self) -> f64 {
+        self.x.hypot(self.y)
+    }
+
+    /// Returns a vector with the same direction as this one but with a length of 1. The zero
+    /// vector has no direction, so it is returned unchanged.
+    pub fn normalized(self) -> Self {
+        if self.is_zero() {
+            return self;
+        }
+
+        let length = self.length();
+        Self::new(self.x / length, self.y / length)
+    }
+}
+
+impl Add for Vector {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vector {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f64> for Vector {
+    type Output = Self;
+
+    fn mul(self, scalar: f64) -> Self {
+        Self::new(self.x * scalar, self.y * scalar)
+    }
+}
@@ -0,0 +1,87 @@
+//! The world and the screen use different coordinate systems. The screen has (0, 0) in the
+//! top-left corner of the window and is measured in screen pixels, while the world is seen
+//! through a camera that can be anywhere and zoomed in or out. Both are just numbers, so it is
+//! easy to accidentally pass a position in one system to something expecting the other.
+//!
+//! To prevent that, positions in each coordinate system have their own type and everything is
+//! drawn through a `WorldCanvas`, which only accepts world coordinates and does the conversion to
+//! screen coordinates itself.
+
+use sdl2::{
+    rect::{Point, Rect},
+    render::{WindowCanvas, Texture},
+};
+
+use crate::vector::Vector;
+
+/// A point in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldPoint(pub Vector);
+
+/// A rectangle in world coordinates
+///
+/// Unlike `Rect`, this can be positioned and sized in fractions of a pixel. It only gets rounded to
+/// whole pixels once it is converted to screen coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldRect {
+    pub top_left: WorldPoint,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A point in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenPoint(pub Point);
+
+/// A rectangle in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenRect(pub Rect);
+
+/// A canvas that is drawn on using world coordinates, as seen by a camera
+pub struct WorldCanvas<'a> {
+    canvas: &'a mut WindowCanvas,
+    /// The point in world coordinates shown at the center of the screen
+    camera_pos: Vector,
+    /// The number of screen pixels used to draw each world pixel
+    zoom: f64,
+    /// The size of the canvas in screen pixels, fetched once since it can't change while drawing
+    screen_size: (u32, u32),
+}
+
+impl<'a> WorldCanvas<'a> {
+    /// Wraps a canvas so that it can be drawn on as seen by a camera at the given position with
+    /// the given zoom
+    pub fn new(canvas: &'a mut WindowCanvas, camera_pos: Vector, zoom: f64) -> Result<Self, String> {
+        let screen_size = canvas.output_size()?;
+        Ok(Self {canvas, camera_pos, zoom, screen_size})
+    }
+
+    /// Converts a point in world coordinates to screen coordinates
+    pub fn to_screen_point(&self, WorldPoint(point): WorldPoint) -> ScreenPoint {
+        let (width, height) = self.screen_size;
+        let screen_center = Vector::new(width as f64 / 2.0, height as f64 / 2.0);
+        // Things can only be drawn at whole pixels
+        ScreenPoint(((point - self.camera_pos) * self.zoom + screen_center).to_point())
+    }
+
+    /// Converts a rectangle in world coordinates to screen coordinates
+    pub fn to_screen_rect(&self, rect: WorldRect) -> ScreenRect {
+        let ScreenPoint(top_left) = self.to_screen_point(rect.top_left);
+        let width = (rect.width * self.zoom).round() as u32;
+        let height = (rect.height * self.zoom).round() as u32;
+        ScreenRect(Rect::new(top_left.x(), top_left.y(), width, height))
+    }
+
+    /// Returns true if any part of the given area of the world is on the screen
+    pub fn is_visible(&self, area: WorldRect) -> bool {
+        let ScreenRect(screen_rect) = self.to_screen_rect(area);
+        let (width, height) = self.screen_size;
+        screen_rect.has_intersection(Rect::new(0, 0, width, height))
+    }
+
+    /// Copies the given region of a texture onto the given area of the world
+    pub fn copy(&mut self, texture: &Texture, region: Rect, dest: WorldRect) -> Result<(), String> {
+        let ScreenRect(screen_rect) = self.to_screen_rect(dest);
+        self.canvas.copy(texture, region, screen_rect)
+    }
+}
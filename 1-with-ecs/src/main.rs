@@ -3,7 +3,7 @@ mod components;
 mod resources;
 mod systems;
 //TODO(BONUS#1): Remove this line or else your solution will never run!
-#[path = "../../1-with-ecs-solutions/src/renderer.rs"]
+#[path = "renderer_solution.rs"]
 mod renderer;
 
 //TODO(EX#4): You may need to modify the imports below.
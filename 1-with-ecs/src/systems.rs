@@ -1,9 +1,9 @@
 //TODO(BONUS#2): Remove this line or else your solution will never run!
-#[path = "../../1-with-ecs-solutions/src/systems/keyboard.rs"]
+#[path = "systems/keyboard_solution.rs"]
 mod keyboard;
 mod movement;
 //TODO(BONUS#3): Remove this line or else your solution will never run!
-#[path = "../../1-with-ecs-solutions/src/systems/animator.rs"]
+#[path = "systems/animator_solution.rs"]
 mod animator;
 mod ai;
 mod win_lose_checker;